@@ -1,15 +1,25 @@
 extern crate anitomy;
 
-use anitomy::{Anitomy, ElementCategory};
+use std::collections::HashMap;
+use std::fmt;
+
+use anitomy::{Anitomy, ElementCategory, Elements, Options};
+
+use crate::schema::keys;
 
 #[cfg(feature = "regex")]
 use regex::Captures;
 
-#[derive(Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum EpisodeType {
     Ending,
     Episode,
     Movie,
+    NCED,
+    NCOP,
+    OAD,
+    ONA,
     Opening,
     Other,
     OVA,
@@ -18,12 +28,36 @@ pub enum EpisodeType {
 }
 
 impl EpisodeType {
+    pub const ALL: [EpisodeType; 12] = [
+        EpisodeType::Ending,
+        EpisodeType::Episode,
+        EpisodeType::Movie,
+        EpisodeType::NCED,
+        EpisodeType::NCOP,
+        EpisodeType::OAD,
+        EpisodeType::ONA,
+        EpisodeType::Opening,
+        EpisodeType::Other,
+        EpisodeType::OVA,
+        EpisodeType::Preview,
+        EpisodeType::Special,
+    ];
+
+    pub fn from_name(name: &str) -> Option<EpisodeType> {
+        let name = name.to_lowercase();
+        Self::ALL.into_iter().find(|t| t.to_string() == name)
+    }
+
     fn from_element(value: Option<&str>) -> EpisodeType {
         match value {
             None => EpisodeType::Episode,
             Some("ED") => EpisodeType::Ending,
             Some("Gekijouban") => EpisodeType::Movie,
             Some("Movie") => EpisodeType::Movie,
+            Some("NCED") => EpisodeType::NCED,
+            Some("NCOP") => EpisodeType::NCOP,
+            Some("OAD") => EpisodeType::OAD,
+            Some("ONA") => EpisodeType::ONA,
             Some("OP") => EpisodeType::Opening,
             Some("OVA") => EpisodeType::OVA,
             Some("Preview") => EpisodeType::Preview,
@@ -35,59 +69,520 @@ impl EpisodeType {
     }
 }
 
-#[derive(Debug)]
+impl fmt::Display for EpisodeType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            EpisodeType::Ending => "ending",
+            EpisodeType::Episode => "episode",
+            EpisodeType::Movie => "movie",
+            EpisodeType::NCED => "nced",
+            EpisodeType::NCOP => "ncop",
+            EpisodeType::OAD => "oad",
+            EpisodeType::ONA => "ona",
+            EpisodeType::Opening => "opening",
+            EpisodeType::Other => "other",
+            EpisodeType::OVA => "ova",
+            EpisodeType::Preview => "preview",
+            EpisodeType::Special => "special",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Additional mappings from anitomy type strings to episode types, which take
+// precedence over the built-in mapping.
+#[derive(Clone, Debug, Default)]
+pub struct TypeMapping {
+    overrides: HashMap<String, EpisodeType>,
+}
+
+impl TypeMapping {
+    pub fn insert(&mut self, value: &str, episode_type: EpisodeType) {
+        self.overrides.insert(value.to_lowercase(), episode_type);
+    }
+
+    pub fn get(&self, value: Option<&str>) -> EpisodeType {
+        let overridden = value.and_then(|value| self.overrides.get(&value.to_lowercase()));
+        match overridden {
+            Some(episode_type) => *episode_type,
+            None => EpisodeType::from_element(value),
+        }
+    }
+}
+
+// Everything that affects how filenames are parsed with anitomy.
+#[derive(Clone, Debug, Default)]
+pub struct Parser {
+    pub options: Options,
+    // Strings removed from filenames before parsing.
+    pub strip: Vec<String>,
+    pub types: TypeMapping,
+}
+
+impl Parser {
+    fn parse(&self, filename: &str) -> Elements {
+        let mut filename = filename.to_string();
+        for string in &self.strip {
+            filename = filename.replace(string.as_str(), "");
+        }
+        let mut anitomy = Anitomy::new();
+        anitomy.set_options(&self.options);
+        match anitomy.parse(filename.trim()) {
+            Ok(elements) => elements,
+            Err(elements) => elements,
+        }
+    }
+}
+
+// Anitomy leaves the anime type at the end of the title ("Title - OVA"), which would
+// prevent specials from being matched to the rule of the show.
+fn strip_type(title: &str, anime_type: &str) -> String {
+    let delimiters = [' ', '-', '_', '.'];
+    let Some(stripped) = title
+        .len()
+        .checked_sub(anime_type.len())
+        .filter(|index| title.is_char_boundary(*index))
+        .filter(|index| title[*index..].eq_ignore_ascii_case(anime_type))
+        .map(|index| &title[..index])
+    else {
+        return title.to_string();
+    };
+    if !stripped.ends_with(delimiters) {
+        return title.to_string();
+    }
+    let stripped = stripped.trim_end_matches(delimiters);
+    if stripped.is_empty() {
+        title.to_string()
+    } else {
+        stripped.to_string()
+    }
+}
+
+fn parse_short_season(value: &str) -> Option<i64> {
+    value
+        .strip_prefix(['S', 's'])
+        .filter(|number| !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()))
+        .and_then(|number| number.parse().ok())
+}
+
+// Anitomy doesn't recognize the short "Title S2" form of seasons, so the season is split
+// from the end of the title.
+fn split_season(title: String) -> (String, Option<i64>) {
+    let Some((rest, suffix)) = title.rsplit_once(' ') else {
+        return (title, None);
+    };
+    match parse_short_season(suffix) {
+        Some(season) => (rest.trim_end().to_string(), Some(season)),
+        None => (title, None),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QualityCategory {
+    Audio,
+    Resolution,
+    Source,
+    Video,
+}
+
+impl QualityCategory {
+    pub const ALL: [QualityCategory; 4] = [
+        QualityCategory::Audio,
+        QualityCategory::Resolution,
+        QualityCategory::Source,
+        QualityCategory::Video,
+    ];
+
+    pub fn from_name(name: &str) -> Option<QualityCategory> {
+        Self::ALL.into_iter().find(|c| c.to_string() == name)
+    }
+
+    // Turns the different spellings of the same term into a single form, so that
+    // "1920x1080" matches "1080p" and "x264" matches "AVC".
+    pub fn canonicalize(&self, value: &str) -> String {
+        let value = value.trim();
+        let upper = value.to_uppercase();
+        match self {
+            QualityCategory::Resolution => {
+                let height = match upper.split_once('X') {
+                    Some((_, height)) => height,
+                    None => upper.trim_end_matches(['P', 'I']),
+                };
+                match height.parse::<u32>() {
+                    Ok(height) => format!("{}p", height),
+                    Err(_) => value.to_lowercase(),
+                }
+            }
+            QualityCategory::Source => match upper.as_str() {
+                "BD" | "BDRIP" | "BLURAY" | "BLU-RAY" | "BD-RIP" => "BD".to_string(),
+                "WEB" | "WEB-DL" | "WEBDL" | "WEBRIP" | "WEB-RIP" => "WEB".to_string(),
+                "DVD" | "DVDRIP" | "DVD-RIP" => "DVD".to_string(),
+                "TV" | "TVRIP" | "TV-RIP" | "HDTV" => "TV".to_string(),
+                _ => upper,
+            },
+            QualityCategory::Video => match upper.as_str() {
+                "AVC" | "H264" | "H.264" | "X264" => "AVC".to_string(),
+                "HEVC" | "H265" | "H.265" | "X265" => "HEVC".to_string(),
+                _ => upper,
+            },
+            QualityCategory::Audio => upper,
+        }
+    }
+}
+
+impl fmt::Display for QualityCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            QualityCategory::Audio => keys::AUDIO,
+            QualityCategory::Resolution => keys::RESOLUTION,
+            QualityCategory::Source => keys::SOURCE,
+            QualityCategory::Video => keys::VIDEO,
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Quality {
+    pub resolution: Option<String>,
+    pub source: Option<String>,
+    pub video: Vec<String>,
+    pub audio: Vec<String>,
+}
+
+impl Quality {
+    fn from_elements(elements: &Elements, parent: Option<&Quality>) -> Quality {
+        let all = |category: ElementCategory, quality_category: QualityCategory| {
+            elements
+                .get_all(category)
+                .iter()
+                .map(|value| quality_category.canonicalize(value))
+                .collect::<Vec<String>>()
+        };
+        let resolution = elements
+            .get(ElementCategory::VideoResolution)
+            .map(|value| QualityCategory::Resolution.canonicalize(value));
+        let source = elements
+            .get(ElementCategory::Source)
+            .map(|value| QualityCategory::Source.canonicalize(value));
+        let video = all(ElementCategory::VideoTerm, QualityCategory::Video);
+        let audio = all(ElementCategory::AudioTerm, QualityCategory::Audio);
+        match parent {
+            Some(parent) => Quality {
+                resolution: resolution.or_else(|| parent.resolution.clone()),
+                source: source.or_else(|| parent.source.clone()),
+                video: if video.is_empty() {
+                    parent.video.clone()
+                } else {
+                    video
+                },
+                audio: if audio.is_empty() {
+                    parent.audio.clone()
+                } else {
+                    audio
+                },
+            },
+            None => Quality {
+                resolution,
+                source,
+                video,
+                audio,
+            },
+        }
+    }
+
+    pub fn get(&self, category: QualityCategory) -> Vec<&str> {
+        match category {
+            QualityCategory::Audio => self.audio.iter().map(String::as_str).collect(),
+            QualityCategory::Resolution => self.resolution.iter().map(String::as_str).collect(),
+            QualityCategory::Source => self.source.iter().map(String::as_str).collect(),
+            QualityCategory::Video => self.video.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+// Episode numbers may have decimals ("12.5") and part suffixes ("13a", "05'"). The
+// number is used for comparisons against thresholds and ranges, while the part
+// separates the different parts of the same episode number from each other.
+#[derive(Clone, Debug)]
+pub struct EpisodeNumber {
+    pub number: f64,
+    part: String,
+    raw: String,
+}
+
+impl EpisodeNumber {
+    pub fn parse(value: &str) -> Option<EpisodeNumber> {
+        let raw = value.trim();
+        let number_length = raw
+            .char_indices()
+            .take_while(|(index, c)| c.is_ascii_digit() || (*c == '.' && *index > 0))
+            .count();
+        let number_part = raw[..number_length].trim_end_matches('.');
+        let number = number_part.parse().ok()?;
+        let part = raw[number_part.len()..].trim().to_lowercase();
+        Some(EpisodeNumber {
+            number,
+            part,
+            raw: raw.to_string(),
+        })
+    }
+
+    pub fn is_integer(&self, integer: i64) -> bool {
+        self.number == integer as f64
+    }
+
+    pub fn is_below(&self, integer: i64) -> bool {
+        self.number < integer as f64
+    }
+
+    pub fn is_above(&self, integer: i64) -> bool {
+        self.number > integer as f64
+    }
+}
+
+impl PartialEq for EpisodeNumber {
+    fn eq(&self, other: &Self) -> bool {
+        self.number == other.number && self.part == other.part
+    }
+}
+
+impl fmt::Display for EpisodeNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Release {
     pub title: String,
     pub group: String,
-    pub episode: String,
+    pub episode: Option<EpisodeNumber>,
     pub version: i32,
     pub episode_type: EpisodeType,
+    pub anime_type: Option<String>,
+    pub quality: Quality,
+    pub season: Option<i64>,
+    pub year: Option<i32>,
+    pub checksum: Option<String>,
 }
 
 impl Release {
-    pub fn from(filename: &str) -> Option<Release> {
-        let mut anitomy = Anitomy::new();
-        let elements = match anitomy.parse(filename) {
-            Ok(elements) => elements,
-            Err(elements) => elements,
+    pub fn from(filename: &str, parser: &Parser) -> Option<Release> {
+        Self::parse(filename, None, parser)
+    }
+
+    // Parses a file contained in a release directory. Title, group, version, season,
+    // year, type and quality are inherited from the directory release if the filename
+    // itself lacks them.
+    pub fn from_parent(filename: &str, parent: &Release, parser: &Parser) -> Option<Release> {
+        Self::parse(filename, Some(parent), parser)
+    }
+
+    // Subfolders of a release directory ("Season 2", "Specials") override the season, year
+    // and type of the directory release. The title is only replaced when the folder has a
+    // season or a year as well, since other folders ("Extras", "Disc 1") aren't titles.
+    pub fn from_folder(name: &str, parent: &Release, parser: &Parser) -> Release {
+        let elements = parser.parse(name);
+        let anime_type = elements.get(ElementCategory::AnimeType);
+        let title = elements
+            .get(ElementCategory::AnimeTitle)
+            .map(|title| match anime_type {
+                Some(anime_type) => strip_type(title, anime_type),
+                None => title.to_string(),
+            })
+            .unwrap_or_default();
+        let (title, title_season) = match parse_short_season(&title) {
+            Some(season) => (String::new(), Some(season)),
+            None => split_season(title),
         };
+        let season = elements
+            .get(ElementCategory::AnimeSeason)
+            .and_then(|season| season.parse().ok())
+            .or(title_season);
+        let year = elements
+            .get(ElementCategory::AnimeYear)
+            .and_then(|year| year.parse().ok());
 
-        let title = elements.get(ElementCategory::AnimeTitle)?.to_string();
-        let group = elements.get(ElementCategory::ReleaseGroup)?.to_string();
+        let mut release = parent.clone();
+        if !title.is_empty() && (season.is_some() || year.is_some()) {
+            release.title = title;
+        }
+        if let Some(anime_type) = anime_type {
+            release.episode_type = parser.types.get(Some(anime_type));
+            release.anime_type = Some(anime_type.to_string());
+        }
+        release.season = season.or(release.season);
+        release.year = year.or(release.year);
+        release
+    }
+
+    fn parse(filename: &str, parent: Option<&Release>, parser: &Parser) -> Option<Release> {
+        let elements = parser.parse(filename);
+
+        // Files of release directories are often named after the episode number or the
+        // type alone ("01.mkv", "SP1.mkv"), which anitomy parses as the title.
+        let numbered = match (parent, elements.get(ElementCategory::EpisodeNumber)) {
+            (Some(_), None) => elements
+                .get(ElementCategory::AnimeTitle)
+                .filter(|title| title.chars().all(|c| c.is_ascii_digit() || c == '.'))
+                .and_then(EpisodeNumber::parse),
+            _ => None,
+        };
+        let is_type = |title: &str| {
+            elements
+                .get(ElementCategory::AnimeType)
+                .is_some_and(|anime_type| title.eq_ignore_ascii_case(anime_type))
+        };
+        let title = match elements.get(ElementCategory::AnimeTitle) {
+            Some(_) if numbered.is_some() => parent?.title.clone(),
+            Some(title) if parent.is_some() && is_type(title) => parent?.title.clone(),
+            Some(title) => match elements.get(ElementCategory::AnimeType) {
+                Some(anime_type) => strip_type(title, anime_type),
+                None => title.to_string(),
+            },
+            None => parent?.title.clone(),
+        };
+        let (title, title_season) = split_season(title);
+        let season = elements
+            .get(ElementCategory::AnimeSeason)
+            .and_then(|season| season.parse().ok())
+            .or(title_season)
+            .or_else(|| parent.and_then(|parent| parent.season));
+        let group = match elements.get(ElementCategory::ReleaseGroup) {
+            Some(group) => group.to_string(),
+            None => parent?.group.clone(),
+        };
         let episode = elements
             .get(ElementCategory::EpisodeNumber)
-            .map_or_else(|| "-1".to_string(), |v| v.to_string());
-        let version: i32 = elements
-            .get(ElementCategory::ReleaseVersion)
-            .map_or(1, |v| v.parse().unwrap_or(1));
-        let episode_type = EpisodeType::from_element(elements.get(ElementCategory::AnimeType));
+            .and_then(EpisodeNumber::parse)
+            .or(numbered);
+        let version: i32 = match elements.get(ElementCategory::ReleaseVersion) {
+            Some(version) => version.parse().unwrap_or(1),
+            None => parent.map_or(1, |parent| parent.version),
+        };
+        let anime_type = elements.get(ElementCategory::AnimeType);
+        let (episode_type, anime_type) = match (anime_type, parent) {
+            (None, Some(parent)) => (parent.episode_type, parent.anime_type.clone()),
+            _ => (
+                parser.types.get(anime_type),
+                anime_type.map(|v| v.to_string()),
+            ),
+        };
+        let quality = Quality::from_elements(&elements, parent.map(|parent| &parent.quality));
+        let year = elements
+            .get(ElementCategory::AnimeYear)
+            .and_then(|year| year.parse().ok())
+            .or_else(|| parent.and_then(|parent| parent.year));
+        let checksum = elements
+            .get(ElementCategory::FileChecksum)
+            .map(|v| v.to_uppercase());
         Some(Release {
             title,
             group,
             episode,
             version,
             episode_type,
+            anime_type,
+            quality,
+            season,
+            year,
+            checksum,
         })
     }
 
     #[cfg(feature = "regex")]
-    pub fn from_captures(title: &str, captures: Captures) -> Option<Release> {
+    pub fn from_captures(title: &str, captures: Captures, parser: &Parser) -> Option<Release> {
         let group = captures.name("group")?.as_str();
-        let episode = captures.name("episode")?.as_str();
+        let episode = EpisodeNumber::parse(captures.name("episode")?.as_str());
         let version: i32 = match captures.name("version") {
             Some(version) => version.as_str().parse().unwrap_or(1),
             None => 1,
         };
+        let anime_type = captures.name("type").map(|v| v.as_str());
+        let episode_type = parser.types.get(anime_type);
+        let anime_type = anime_type.map(|v| v.to_string());
+        let quality = Quality {
+            resolution: captures
+                .name("resolution")
+                .map(|v| QualityCategory::Resolution.canonicalize(v.as_str())),
+            ..Quality::default()
+        };
+        let season = captures
+            .name("season")
+            .and_then(|season| season.as_str().parse().ok());
+        let checksum = captures.name("crc").map(|crc| crc.as_str().to_uppercase());
         Some(Release {
             title: title.to_string(),
             group: group.to_string(),
-            episode: episode.to_string(),
+            episode,
             version,
-            episode_type: EpisodeType::Episode,
+            episode_type,
+            anime_type,
+            quality,
+            season,
+            year: None,
+            checksum,
         })
     }
 
-    pub fn numerical_episode(&self) -> Option<i32> {
-        self.episode.parse().ok()
+    // Releases without a season are considered to be from the first season.
+    pub fn season_or_first(&self) -> i64 {
+        self.season.unwrap_or(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_episode_numbers() {
+        let episode = EpisodeNumber::parse(" 07 ").unwrap();
+        assert_eq!(episode.number, 7.0);
+        assert_eq!(episode.part, "");
+        assert_eq!(episode.to_string(), "07");
+        assert!(episode.is_integer(7));
+
+        let episode = EpisodeNumber::parse("12.5").unwrap();
+        assert_eq!(episode.number, 12.5);
+        assert!(!episode.is_integer(12));
+        assert!(episode.is_above(12) && episode.is_below(13));
+
+        assert!(EpisodeNumber::parse("abc").is_none());
+        assert!(EpisodeNumber::parse(".5").is_none());
+        assert!(EpisodeNumber::parse("").is_none());
+    }
+
+    #[test]
+    fn compares_episode_parts() {
+        let parse = |value| EpisodeNumber::parse(value).unwrap();
+        assert_eq!(parse("13a").number, 13.0);
+        assert_eq!(parse("13a"), parse("13A"));
+        assert_ne!(parse("13a"), parse("13b"));
+        assert_ne!(parse("13a"), parse("13"));
+        assert_eq!(parse("05'").part, "'");
+        assert_ne!(parse("12"), parse("12.5"));
+    }
+
+    #[test]
+    fn splits_short_seasons() {
+        let split = |title: &str| split_season(title.to_string());
+        assert_eq!(split("Title S2"), ("Title".to_string(), Some(2)));
+        assert_eq!(split("Title s02"), ("Title".to_string(), Some(2)));
+        assert_eq!(split("Title S"), ("Title S".to_string(), None));
+        assert_eq!(split("Title SS2"), ("Title SS2".to_string(), None));
+        assert_eq!(split("Title Season"), ("Title Season".to_string(), None));
+        assert_eq!(split("S2"), ("S2".to_string(), None));
+    }
+
+    #[test]
+    fn strips_types() {
+        assert_eq!(strip_type("Title OVA", "OVA"), "Title");
+        assert_eq!(strip_type("Title - ova", "OVA"), "Title");
+        assert_eq!(strip_type("TitleOVA", "OVA"), "TitleOVA");
+        assert_eq!(strip_type("OVA", "OVA"), "OVA");
+        assert_eq!(strip_type("Title", "Special"), "Title");
+        assert_eq!(strip_type("日本", "a"), "日本");
     }
 }
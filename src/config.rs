@@ -6,24 +6,39 @@ extern crate directories;
 use std::collections::HashMap;
 use std::env;
 use std::fmt;
+use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use yaml_rust::{yaml, Yaml, YamlLoader};
+use yaml_rust::{yaml, Yaml};
+
+use crate::anime::{EpisodeNumber, EpisodeType, Parser, Quality, QualityCategory, Release};
+use crate::format::{self, Format};
+use crate::schema::{self, keys};
+use crate::title;
 
 #[cfg(feature = "regex")]
 use regex::Regex;
 
+const RULES_DIRECTORY: &str = "rules.d";
+
+const CONFIG_VARIABLE: &str = "YURIZAKI_CONFIG";
+const SOURCE_VARIABLE: &str = "YURIZAKI_SOURCE";
+const LIBRARY_VARIABLE: &str = "YURIZAKI_LIBRARY";
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
 type StringVec = Vec<String>;
 
 trait StringVecMethods {
-    fn yaml_array_to_vec(array: &Vec<Yaml>) -> Option<StringVec>;
+    fn yaml_array_to_vec(array: &[Yaml]) -> Option<StringVec>;
 }
 
 impl StringVecMethods for StringVec {
-    fn yaml_array_to_vec(array: &Vec<Yaml>) -> Option<StringVec> {
+    fn yaml_array_to_vec(array: &[Yaml]) -> Option<StringVec> {
         let mut vec = StringVec::new();
         for item in array {
             let Some(item) = item.as_str() else { continue };
@@ -33,130 +48,317 @@ impl StringVecMethods for StringVec {
     }
 }
 
+type GroupScores = HashMap<String, i64>;
 type RuleList = Vec<Rule>;
-type RuleMapping = HashMap<String, usize>;
+type RuleMapping = HashMap<String, Vec<usize>>;
 
 #[cfg(feature = "regex")]
 type RuleRegexes = Vec<(Regex, usize)>;
 
 #[derive(Debug)]
 pub enum Error {
-    Io(io::Error),
+    // The path is the main configuration file or one of its included files.
+    Io(PathBuf, io::Error),
     MissingLibrary,
     MissingSource,
-    YamlError,
+    Yaml,
+    #[cfg(feature = "toml")]
+    Toml,
+    #[cfg(feature = "json")]
+    Json,
+}
+
+// How changes in the source directory are detected. Native file system events aren't
+// delivered for changes made by other hosts on network file systems, which have to be
+// polled instead.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchMode {
+    Auto,
+    Native,
+    Poll,
+}
+
+impl WatchMode {
+    pub const ALL: [WatchMode; 3] = [WatchMode::Auto, WatchMode::Native, WatchMode::Poll];
+
+    fn from_name(name: &str) -> Option<WatchMode> {
+        Self::ALL.into_iter().find(|mode| mode.to_string() == name)
+    }
+}
+
+impl fmt::Display for WatchMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            WatchMode::Auto => "auto",
+            WatchMode::Native => "native",
+            WatchMode::Poll => "poll",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 #[derive(Debug)]
 pub struct Configuration {
+    pub files: Vec<PathBuf>,
+    pub rules_directory: PathBuf,
     pub rules: RuleList,
     mapping: RuleMapping,
     pub source: PathBuf,
     pub library: PathBuf,
+    pub sidecars: Sidecars,
+    pub media: Media,
+    pub parser: Parser,
+    fuzzy_threshold: Option<f64>,
+    pub watch_mode: WatchMode,
+    pub poll_interval: Duration,
+    pub rescan_interval: Option<Duration>,
+    pub reconcile_on_startup: bool,
 
     #[cfg(feature = "trash")]
     pub trash: bool,
 
     #[cfg(feature = "regex")]
     pub regexes: RuleRegexes,
+
+    #[cfg(feature = "regex")]
+    title_patterns: RuleRegexes,
+
+    #[cfg(feature = "regex")]
+    pub global_regexes: Vec<Regex>,
 }
 
-fn get_config_from_args() -> Option<PathBuf> {
-    let path = env::args().nth(1)?;
-    Some(PathBuf::from(path))
+// The configuration path can be given as an argument or with an environment variable.
+fn get_config_from_argument(argument: Option<String>) -> Option<PathBuf> {
+    if let Some(path) = argument {
+        return Some(expand_path(&path));
+    }
+    let path = env::var(CONFIG_VARIABLE).ok()?;
+    Some(expand_path(&path))
 }
 
 #[cfg(feature = "directories")]
-pub fn get_path() -> Option<PathBuf> {
-    if let Some(config_path) = get_config_from_args() {
+pub fn get_path(argument: Option<String>) -> Option<PathBuf> {
+    if let Some(config_path) = get_config_from_argument(argument) {
         return Some(config_path);
     }
     let project_directory = directories::ProjectDirs::from("", "", "yurizaki")?;
-    let mut config_path = PathBuf::new();
-    config_path.push(project_directory.config_dir());
-    config_path.push("config.yml");
+    let config_directory = project_directory.config_dir();
+    // The first existing configuration file of any supported format is used.
+    let config_path = Format::ALL
+        .iter()
+        .flat_map(|format| format.extensions())
+        .map(|extension| config_directory.join(format!("config.{}", extension)))
+        .find(|path| path.is_file())
+        .unwrap_or_else(|| config_directory.join("config.yml"));
     Some(config_path)
 }
 
 #[cfg(not(feature = "directories"))]
-pub fn get_path() -> Option<PathBuf> {
-    return get_config_from_args();
+pub fn get_path(argument: Option<String>) -> Option<PathBuf> {
+    get_config_from_argument(argument)
 }
 
 impl Configuration {
     pub fn new(path: &PathBuf) -> Result<Configuration, Error> {
-        let file_content = match load_file_to_string(path) {
-            Ok(string) => string,
-            Err(e) => return Err(Error::Io(e)),
-        };
-        let yaml_vector = match YamlLoader::load_from_str(&file_content) {
-            Ok(config) => config,
-            Err(_) => return Err(Error::YamlError),
-        };
-        let yaml_document = &yaml_vector.get(0);
-        let configuration_yaml = match yaml_document {
-            Some(yaml) => yaml,
-            None => return Err(Error::YamlError),
-        };
+        let mut files = vec![path.clone()];
+        let mut merged = load_hash(path)?;
+
+        // Included files and the files in the rules directory are merged into the main
+        // configuration. Keys that were already defined earlier take precedence.
+        let config_directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let mut include_paths = Vec::new();
+        if let Some(include) = merged.remove(&Yaml::String(keys::INCLUDE.to_string())) {
+            let include = match include {
+                Yaml::String(path) => vec![path],
+                Yaml::Array(array) => StringVec::yaml_array_to_vec(&array).unwrap_or_default(),
+                _ => Vec::new(),
+            };
+            include_paths.extend(
+                include
+                    .iter()
+                    .map(|p| config_directory.join(expand_path(p))),
+            );
+        }
+        let rules_directory = config_directory.join(RULES_DIRECTORY);
+        if let Ok(entries) = fs::read_dir(&rules_directory) {
+            let mut rule_paths: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_file() && format::is_config_file(path))
+                .collect();
+            rule_paths.sort();
+            include_paths.extend(rule_paths);
+        }
+        for include_path in include_paths {
+            let included = load_hash(&include_path)?;
+            for (key, value) in included {
+                if merged.contains_key(&key) {
+                    log::warn!(
+                        "Key '{}' in \"{}\" is already defined and is ignored",
+                        key.as_str().unwrap_or("?"),
+                        include_path.display()
+                    );
+                    continue;
+                }
+                merged.insert(key, value);
+            }
+            files.push(include_path);
+        }
+        schema::check_configuration(&merged);
+        let inheritance = RuleInheritance::read(&mut merged);
+        let configuration_yaml = Yaml::Hash(merged);
 
-        let mut library: Option<PathBuf> = None;
+        let mut library: Option<String> = None;
         let mut mapping: RuleMapping = RuleMapping::new();
         let mut rules = RuleList::new();
+        let mut sidecars = Sidecars::default();
+        let mut media = Media::default();
+        let mut fuzzy_threshold: Option<f64> = None;
+        let mut watch_mode = WatchMode::Auto;
+        let mut poll_interval = DEFAULT_POLL_INTERVAL;
+        let mut rescan_interval: Option<Duration> = None;
+        let mut reconcile_on_startup = false;
+        let mut parser = Parser::default();
         let mut source_path: Option<String> = None;
         let mut trash: bool = false;
 
         #[cfg(feature = "regex")]
         let mut regexes = RuleRegexes::new();
+        #[cfg(feature = "regex")]
+        let mut title_patterns = RuleRegexes::new();
+        #[cfg(feature = "regex")]
+        let mut global_regexes = Vec::new();
 
         if let Some(configuration_yaml) = configuration_yaml.as_hash() {
             for (key, value) in configuration_yaml {
                 match (key.as_str(), value) {
-                    (Some("library"), Yaml::String(value)) => {
-                        library = Some(PathBuf::from(value));
+                    (Some(keys::LIBRARY), Yaml::String(value)) => {
+                        library = Some(value.clone());
                     }
-                    (Some("source"), Yaml::String(value)) => {
+                    (Some(keys::SOURCE), Yaml::String(value)) => {
                         source_path = Some(value.clone());
                     }
-                    (Some("trash"), Yaml::Boolean(value)) => {
+                    (Some(keys::TRASH), Yaml::Boolean(value)) => {
                         trash = *value;
                     }
+                    (Some(keys::SIDECARS), Yaml::Hash(hash)) => {
+                        sidecars = Sidecars::read(hash);
+                    }
+                    (Some(keys::MEDIA), Yaml::Hash(hash)) => {
+                        media = Media::read(hash);
+                    }
+                    (Some(keys::TYPE_MAPPING), Yaml::Hash(hash)) => {
+                        for (value, name) in hash {
+                            let (Some(value), Some(name)) = (value.as_str(), name.as_str()) else {
+                                continue;
+                            };
+                            match EpisodeType::from_name(name) {
+                                Some(episode_type) => parser.types.insert(value, episode_type),
+                                None => log::warn!("Unknown episode type '{}'", name),
+                            }
+                        }
+                    }
+                    (Some(keys::PARSER), Yaml::Hash(hash)) => {
+                        ParserOverrides::read(hash).apply(&mut parser);
+                    }
+                    (Some(keys::FUZZY_THRESHOLD), Yaml::Real(_)) => {
+                        fuzzy_threshold = value.as_f64();
+                    }
+                    (Some(keys::FUZZY_THRESHOLD), Yaml::Integer(threshold)) => {
+                        fuzzy_threshold = Some(*threshold as f64);
+                    }
+                    (Some(keys::WATCH_MODE), Yaml::String(name)) => {
+                        match WatchMode::from_name(name) {
+                            Some(mode) => watch_mode = mode,
+                            None => log::warn!("Unknown watch mode '{}'", name),
+                        }
+                    }
+                    (Some(keys::POLL_INTERVAL), Yaml::Integer(seconds)) if *seconds > 0 => {
+                        poll_interval = Duration::from_secs(*seconds as u64);
+                    }
+                    (Some(keys::RESCAN_INTERVAL), Yaml::Integer(seconds)) if *seconds > 0 => {
+                        rescan_interval = Some(Duration::from_secs(*seconds as u64));
+                    }
+                    (Some(keys::RECONCILE_ON_STARTUP), Yaml::Boolean(value)) => {
+                        reconcile_on_startup = *value;
+                    }
+                    #[cfg(feature = "regex")]
+                    (Some(keys::REGEX), Yaml::Array(regex_strings)) => {
+                        for regex_string in regex_strings {
+                            if let Some(regex) = Self::parse_regex(regex_string, &["title"]) {
+                                global_regexes.push(regex);
+                            }
+                        }
+                    }
                     (Some(title), Yaml::Hash(hash)) => {
                         let title = title.to_string();
-                        let Some(rule) = Rule::read(hash, title.clone()) else {
+                        let Some(rule) = Rule::read(hash, title.clone(), &inheritance) else {
                             continue;
                         };
                         rules.push(rule);
                         let rule_index = rules.len() - 1;
-                        mapping.insert(title, rule_index);
+                        mapping
+                            .entry(title::normalize(&title))
+                            .or_default()
+                            .push(rule_index);
 
                         let blank_vec = Vec::new();
-                        for alias in value["aliases"].as_vec().unwrap_or(&blank_vec) {
+                        for alias in value[keys::ALIASES].as_vec().unwrap_or(&blank_vec) {
                             let Some(alias) = alias.as_str() else {
                                 continue;
                             };
-                            mapping.insert(alias.to_string(), rule_index);
+                            mapping
+                                .entry(title::normalize(alias))
+                                .or_default()
+                                .push(rule_index);
                         }
 
                         #[cfg(feature = "regex")]
-                        if let Some(regex_strings) = value["regex"].as_vec() {
+                        if let Some(regex_strings) = value[keys::REGEX].as_vec() {
                             for regex_string in regex_strings {
-                                if let Some(regex) = Self::parse_regex(regex_string) {
+                                if let Some(regex) = Self::parse_regex(regex_string, &[]) {
                                     regexes.push((regex, rule_index));
                                 }
                             }
                         }
+
+                        #[cfg(feature = "regex")]
+                        for pattern in Self::yaml_to_strings(&value[keys::TITLE_PATTERN]) {
+                            match Regex::new(&pattern) {
+                                Ok(regex) => title_patterns.push((regex, rule_index)),
+                                Err(_) => log::warn!(
+                                    "Title pattern '{}' could not be parsed as regex",
+                                    pattern
+                                ),
+                            }
+                        }
                     }
                     _ => {}
                 }
             }
         }
 
+        for rule in &mut rules {
+            if let Some(overrides) = &rule.parser_overrides {
+                let mut rule_parser = parser.clone();
+                overrides.apply(&mut rule_parser);
+                rule.parser = Some(rule_parser);
+            }
+        }
+
+        // Environment variables take precedence over the configuration files.
+        if let Ok(value) = env::var(SOURCE_VARIABLE) {
+            source_path = Some(value);
+        }
+        if let Ok(value) = env::var(LIBRARY_VARIABLE) {
+            library = Some(value);
+        }
+
         let source_path = match source_path {
             Some(value) => value,
             None => return Err(Error::MissingSource),
         };
         let library = match library {
-            Some(value) => value,
+            Some(value) => expand_path(&value),
             None => return Err(Error::MissingLibrary),
         };
 
@@ -164,26 +366,96 @@ impl Configuration {
             log::warn!("yurizaki was built without trash support; enabling trash does nothing.");
         }
 
-        let source = PathBuf::from(source_path);
+        // Event paths of the watchers are absolute, so the source has to be as well to
+        // strip it from them.
+        let source = expand_path(&source_path);
+        let source = fs::canonicalize(&source)
+            .or_else(|_| std::path::absolute(&source))
+            .unwrap_or(source);
 
         Ok(Configuration {
+            files,
+            rules_directory,
+            fuzzy_threshold,
+            watch_mode,
+            poll_interval,
+            rescan_interval,
+            reconcile_on_startup,
             library,
             mapping,
+            media,
+            parser,
             rules,
+            sidecars,
             source,
             #[cfg(feature = "trash")]
             trash,
             #[cfg(feature = "regex")]
             regexes,
+            #[cfg(feature = "regex")]
+            title_patterns,
+            #[cfg(feature = "regex")]
+            global_regexes,
         })
     }
 
-    pub fn get_rule(&self, name: &str) -> Option<&Rule> {
-        Some(&self.rules[*self.mapping.get(name)?])
+    // Picks the first of the rules sharing a title whose scope contains the release.
+    fn select_rule(&self, indexes: &[usize], release: &Release) -> Option<&Rule> {
+        indexes
+            .iter()
+            .map(|index| &self.rules[*index])
+            .find(|rule| rule.scope.contains(release))
     }
 
+    pub fn get_rule(&self, release: &Release) -> Option<&Rule> {
+        let name = &release.title;
+        let normalized_name = title::normalize(name);
+        if let Some(indexes) = self.mapping.get(&normalized_name) {
+            return self.select_rule(indexes, release);
+        }
+
+        #[cfg(feature = "regex")]
+        for (regex, index) in &self.title_patterns {
+            let rule = &self.rules[*index];
+            if regex.is_match(name) && rule.scope.contains(release) {
+                log::debug!("Matched title {} to pattern {}", name, regex);
+                return Some(rule);
+            }
+        }
+        let name = normalized_name;
+
+        // Fall back to the most similar title or alias if fuzzy matching is enabled.
+        let threshold = self.fuzzy_threshold?;
+        let (matched, rule, similarity) = self
+            .mapping
+            .iter()
+            .filter_map(|(title, indexes)| Some((title, self.select_rule(indexes, release)?)))
+            .map(|(title, rule)| (title, rule, title::similarity(&name, title)))
+            .filter(|(_, _, similarity)| *similarity >= threshold)
+            .max_by(|(a_title, _, a), (b_title, _, b)| a.total_cmp(b).then(b_title.cmp(a_title)))?;
+        log::info!(
+            "FUZZY: \"{}\" accepted as \"{}\" (similarity {:.2})",
+            name,
+            matched,
+            similarity
+        );
+        Some(rule)
+    }
+
+    // Accepts either a single string or a list of strings.
     #[cfg(feature = "regex")]
-    fn parse_regex(string: &Yaml) -> Option<Regex> {
+    fn yaml_to_strings(value: &Yaml) -> StringVec {
+        match value {
+            Yaml::String(string) => vec![string.clone()],
+            Yaml::Array(array) => StringVec::yaml_array_to_vec(array).unwrap_or_default(),
+            _ => StringVec::new(),
+        }
+    }
+
+    // Every regex must capture the episode and the group, in addition to any of the
+    // given extra capture groups.
+    #[cfg(feature = "regex")]
+    fn parse_regex(string: &Yaml, required_captures: &[&str]) -> Option<Regex> {
         let Some(regex_string) = string.as_str() else {
             log::warn!("Regex value '{:?}' could not be parsed as string", string);
             return None;
@@ -193,21 +465,19 @@ impl Configuration {
             return None;
         };
         let captures = regex.capture_names().flatten().collect::<Vec<&str>>();
-        let has_episode = captures.iter().any(|e| e == &"episode");
-        let has_group = captures.iter().any(|e| e == &"group");
-        if has_episode && has_group {
+        let missing = ["episode", "group"]
+            .iter()
+            .chain(required_captures)
+            .filter(|required| !captures.contains(required))
+            .collect::<Vec<&&str>>();
+        if missing.is_empty() {
             Some(regex)
         } else {
-            if !has_episode {
-                log::warn!(
-                    "Could not use regex '{}': no capture group for 'episode'",
-                    regex,
-                );
-            }
-            if !has_group {
+            for capture in missing {
                 log::warn!(
-                    "Could not use regex '{}': no capture group for 'group'",
+                    "Could not use regex '{}': no capture group for '{}'",
                     regex,
+                    capture,
                 );
             }
             None
@@ -227,39 +497,360 @@ impl fmt::Display for Configuration {
     }
 }
 
+// Reusable parts of rules: named group lists and default values for rule keys.
+#[derive(Default)]
+struct RuleInheritance {
+    defaults: yaml::Hash,
+    group_sets: HashMap<String, Vec<Yaml>>,
+}
+
+impl RuleInheritance {
+    // Takes the `defaults` and `group_sets` keys out of the configuration so that they
+    // are not read as rules.
+    fn read(config: &mut yaml::Hash) -> Self {
+        let mut inheritance = Self::default();
+        if let Some(defaults) = config.remove(&Yaml::String(keys::DEFAULTS.to_string())) {
+            match defaults {
+                Yaml::Hash(hash) => inheritance.defaults = hash,
+                _ => log::warn!("Rule defaults must be a dictionary"),
+            }
+        }
+        if let Some(group_sets) = config.remove(&Yaml::String(keys::GROUP_SETS.to_string())) {
+            for (name, groups) in group_sets.into_hash().unwrap_or_default() {
+                match (name.as_str(), groups) {
+                    (Some(name), Yaml::Array(groups)) => {
+                        inheritance.group_sets.insert(name.to_string(), groups);
+                    }
+                    (Some(name), _) => log::warn!("Group set '{}' must be a list", name),
+                    _ => (),
+                }
+            }
+        }
+        inheritance
+    }
+
+    // Keys of the rule replace the default values as a whole.
+    fn apply_defaults(&self, config: &yaml::Hash) -> yaml::Hash {
+        let mut merged = config.clone();
+        for (key, value) in &self.defaults {
+            if !merged.contains_key(key) {
+                merged.insert(key.clone(), value.clone());
+            }
+        }
+        merged
+    }
+
+    fn group_set(&self, name: &str) -> Vec<Yaml> {
+        match self.group_sets.get(name) {
+            Some(groups) => groups.clone(),
+            None => {
+                log::warn!("Unknown group set '{}'", name);
+                Vec::new()
+            }
+        }
+    }
+
+    // Resolves group set references into a plain list of groups. A set can be referenced
+    // by name (`groups: default`), extended (`groups: {extends: default, prepend: [..],
+    // append: [..]}`) or inserted into a list with a reference item (`[{set: default}, ..]`).
+    fn expand_groups(&self, groups: &Yaml) -> Yaml {
+        match groups {
+            Yaml::String(name) => Yaml::Array(self.group_set(name)),
+            Yaml::Hash(_) => {
+                let Some(name) = groups[keys::EXTENDS].as_str() else {
+                    return groups.clone();
+                };
+                let mut expanded = self.insert_group_sets(&groups[keys::PREPEND]);
+                expanded.extend(self.group_set(name));
+                expanded.extend(self.insert_group_sets(&groups[keys::APPEND]));
+                Yaml::Array(expanded)
+            }
+            Yaml::Array(_) => Yaml::Array(self.insert_group_sets(groups)),
+            _ => groups.clone(),
+        }
+    }
+
+    // Replaces the group set reference items of a list with the groups of the set.
+    fn insert_group_sets(&self, groups: &Yaml) -> Vec<Yaml> {
+        let mut expanded = Vec::new();
+        for item in groups.as_vec().map(Vec::as_slice).unwrap_or_default() {
+            match item[keys::SET].as_str() {
+                Some(name) => expanded.extend(self.group_set(name)),
+                None => expanded.push(item.clone()),
+            }
+        }
+        expanded
+    }
+}
+
 #[derive(Debug)]
 pub struct Rule {
     pub groups: StringVec,
+    scores: GroupScores,
+    group_aliases: HashMap<String, String>,
+    wildcard_score: Option<i64>,
     pub title: String,
     pub minimum: RuleMinimum,
+    pub maximum: RuleMaximum,
+    pub filter: RuleFilter,
+    pub quality: RuleQuality,
+    pub scope: RuleScope,
+    seasons: Option<HashMap<i64, String>>,
+    pub types: RuleTypes,
+    parser_overrides: Option<ParserOverrides>,
+    pub parser: Option<Parser>,
+    pub tie_breakers: Vec<TieBreaker>,
+}
+
+const GROUP_WILDCARD: &str = "*";
+
+fn normalize_group(group: &str) -> String {
+    group.trim().to_lowercase()
 }
 
 impl Rule {
-    fn read(config: &yaml::Hash, title: String) -> Option<Self> {
-        let mut groups: StringVec = Vec::new();
+    fn read(config: &yaml::Hash, title: String, inheritance: &RuleInheritance) -> Option<Self> {
+        let mut group_list: Vec<(String, i64)> = Vec::new();
+        let mut group_aliases = HashMap::new();
+        let mut wildcard_score: Option<i64> = None;
         let mut minimum = RuleMinimum::default();
-        for (key, value) in config {
-            match (key.as_str(), value) {
-                (Some("groups"), Yaml::Array(array)) => {
-                    if let Some(vec) = StringVec::yaml_array_to_vec(&array) {
-                        groups = vec;
+        let mut maximum = RuleMaximum::default();
+        let mut filter = RuleFilter::default();
+        let mut quality = RuleQuality::default();
+        let mut types = RuleTypes::default();
+        let mut seasons: Option<HashMap<i64, String>> = None;
+        let mut parser_overrides: Option<ParserOverrides> = None;
+        let mut tie_breakers = TieBreaker::default_chain();
+        // The scope identifies the rule itself and is never inherited from the defaults.
+        let scope = RuleScope::read(config);
+        let config = inheritance.apply_defaults(config);
+        for (key, value) in &config {
+            let value = match (key.as_str(), value) {
+                (Some(keys::GROUPS), _) => inheritance.expand_groups(value),
+                _ => value.clone(),
+            };
+            match (key.as_str(), &value) {
+                (Some(keys::GROUPS), Yaml::Array(array)) => {
+                    group_list = Self::read_group_tiers(array);
+                    // The wildcard always has the lowest priority with list syntax.
+                    if group_list.iter().any(|(group, _)| group == GROUP_WILDCARD) {
+                        wildcard_score = Some(0);
+                    }
+                }
+                (Some(keys::GROUPS), Yaml::Hash(hash)) => {
+                    group_list = Self::read_group_scores(hash);
+                    wildcard_score = group_list
+                        .iter()
+                        .find(|(group, _)| group == GROUP_WILDCARD)
+                        .map(|(_, score)| *score);
+                }
+                (Some(keys::GROUP_ALIASES), Yaml::Hash(hash)) => {
+                    for (group, aliases) in hash {
+                        let (Some(group), Some(aliases)) = (group.as_str(), aliases.as_vec())
+                        else {
+                            continue;
+                        };
+                        for alias in StringVec::yaml_array_to_vec(aliases).unwrap_or_default() {
+                            group_aliases.insert(normalize_group(&alias), normalize_group(group));
+                        }
                     }
                 }
-                (Some("minimum"), Yaml::Hash(hash)) => {
+                (Some(keys::MINIMUM), Yaml::Hash(hash)) => {
                     minimum = RuleMinimum::read(hash);
                 }
+                (Some(keys::MAXIMUM), Yaml::Hash(hash)) => {
+                    maximum = RuleMaximum::read(hash);
+                }
+                (Some(keys::FILTER), Yaml::Hash(hash)) => {
+                    filter = RuleFilter::read(hash);
+                }
+                (Some(keys::QUALITY), Yaml::Hash(hash)) => {
+                    quality = RuleQuality::read(hash);
+                }
+                (Some(keys::TYPES), Yaml::Hash(hash)) => {
+                    types = RuleTypes::read(hash);
+                }
+                (Some(keys::SEASONS), Yaml::Hash(hash)) => {
+                    seasons = Some(Self::read_seasons(hash));
+                }
+                (Some(keys::PARSER), Yaml::Hash(hash)) => {
+                    parser_overrides = Some(ParserOverrides::read(hash));
+                }
+                (Some(keys::TIE_BREAKERS), Yaml::Array(array)) => {
+                    if let Some(vec) = StringVec::yaml_array_to_vec(array) {
+                        tie_breakers = TieBreaker::read_chain(&vec);
+                    }
+                }
                 _ => (),
             }
         }
+        let groups = group_list.iter().map(|(group, _)| group.clone()).collect();
+        let scores = group_list
+            .into_iter()
+            .filter(|(group, _)| group != GROUP_WILDCARD)
+            .map(|(group, score)| (normalize_group(&group), score))
+            .collect();
         Some(Rule {
             title,
             groups,
+            scores,
+            group_aliases,
+            wildcard_score,
             minimum,
+            maximum,
+            filter,
+            quality,
+            scope,
+            seasons,
+            types,
+            parser_overrides,
+            parser: None,
+            tie_breakers,
         })
     }
 
-    pub fn get_priority(&self, group_name: &str) -> Option<usize> {
-        self.groups.iter().position(|x| x == group_name)
+    // Groups listed from best to worst. Nested lists form tiers of equally ranked groups.
+    fn read_group_tiers(array: &[Yaml]) -> Vec<(String, i64)> {
+        let mut groups = Vec::new();
+        for (index, item) in array.iter().enumerate() {
+            let score = (array.len() - index) as i64;
+            let tier = match item {
+                Yaml::String(group) => vec![group.clone()],
+                Yaml::Array(tier) => StringVec::yaml_array_to_vec(tier).unwrap_or_default(),
+                _ => continue,
+            };
+            for group in tier {
+                groups.push((group, score));
+            }
+        }
+        groups
+    }
+
+    // Groups with explicit numeric scores, where a higher score is better.
+    fn read_group_scores(hash: &yaml::Hash) -> Vec<(String, i64)> {
+        let mut groups = Vec::new();
+        for (key, value) in hash {
+            match (key.as_str(), value) {
+                (Some(group), Yaml::Integer(score)) => {
+                    groups.push((group.to_string(), *score));
+                }
+                (Some(group), _) => {
+                    log::warn!("Score for group '{}' must be an integer", group);
+                }
+                _ => (),
+            }
+        }
+        groups.sort_by(|(a, a_score), (b, b_score)| b_score.cmp(a_score).then(a.cmp(b)));
+        groups
+    }
+
+    // Seasons and the folders they are copied to. A null folder copies the season to
+    // the rule directory itself. Seasons may be strings since TOML and JSON keys always are.
+    fn read_seasons(hash: &yaml::Hash) -> HashMap<i64, String> {
+        let mut seasons = HashMap::new();
+        for (key, value) in hash {
+            let season = key
+                .as_i64()
+                .or_else(|| key.as_str().and_then(|key| key.parse().ok()));
+            let Some(season) = season else {
+                log::warn!("Season '{:?}' must be an integer", key);
+                continue;
+            };
+            let folder = match value {
+                Yaml::String(folder) => folder.clone(),
+                Yaml::Integer(folder) => folder.to_string(),
+                _ => String::new(),
+            };
+            seasons.insert(season, folder);
+        }
+        seasons
+    }
+
+    pub fn accepts_season(&self, release: &Release) -> bool {
+        match &self.seasons {
+            Some(seasons) => seasons.contains_key(&release.season_or_first()),
+            None => true,
+        }
+    }
+
+    pub fn get_season_folder(&self, release: &Release) -> Option<&str> {
+        let seasons = self.seasons.as_ref()?;
+        let folder = seasons.get(&release.season_or_first())?;
+        if folder.is_empty() {
+            None
+        } else {
+            Some(folder)
+        }
+    }
+
+    fn get_listed_score(&self, group_name: &str) -> Option<i64> {
+        let group = normalize_group(group_name);
+        let group = self.group_aliases.get(&group).unwrap_or(&group);
+        self.scores.get(group).copied()
+    }
+
+    // Checks the version and episode filters of the rule.
+    pub fn filter(&self, release: &Release) -> Result<(), FilterReason> {
+        let version = i64::from(release.version);
+        if let Some(minimum) = self.minimum.version.filter(|minimum| *minimum > version) {
+            return Err(FilterReason::MinimumVersion(version, minimum));
+        }
+        if let Some(maximum) = self.maximum.version.filter(|maximum| *maximum < version) {
+            return Err(FilterReason::MaximumVersion(version, maximum));
+        }
+
+        let Some(episode) = &release.episode else {
+            return Ok(());
+        };
+        if self.filter.exclude.iter().any(|e| episode.is_integer(*e)) {
+            return Err(FilterReason::Excluded(episode.clone()));
+        }
+        let group = normalize_group(&release.group);
+        let group = self.group_aliases.get(&group).unwrap_or(&group);
+        if let Some((first, last)) = self.filter.groups.get(group) {
+            if first.is_some_and(|first| episode.is_below(first))
+                || last.is_some_and(|last| episode.is_above(last))
+            {
+                return Err(FilterReason::GroupRange(
+                    episode.clone(),
+                    release.group.clone(),
+                ));
+            }
+        }
+        // Explicitly included episodes bypass the episode thresholds, but not the episode
+        // ranges of groups.
+        if self.filter.include.iter().any(|e| episode.is_integer(*e)) {
+            return Ok(());
+        }
+        if let Some(minimum) = self
+            .minimum
+            .episode_number
+            .filter(|minimum| episode.is_below(*minimum))
+        {
+            return Err(FilterReason::MinimumEpisode(episode.clone(), minimum));
+        }
+        if let Some(maximum) = self
+            .maximum
+            .episode_number
+            .filter(|maximum| episode.is_above(*maximum))
+        {
+            return Err(FilterReason::MaximumEpisode(episode.clone(), maximum));
+        }
+        Ok(())
+    }
+
+    // Groups are matched case-insensitively and through aliases. Collaborations such as
+    // "GroupA & GroupB" get the best score of their members, and any other group falls
+    // back to the wildcard score if the rule has one.
+    pub fn get_score(&self, group_name: &str) -> Option<i64> {
+        if let Some(score) = self.get_listed_score(group_name) {
+            return Some(score);
+        }
+        let collab_score = group_name
+            .split(['&', '+'])
+            .filter_map(|member| self.get_listed_score(member))
+            .max();
+        collab_score.or(self.wildcard_score)
     }
 }
 
@@ -269,29 +860,668 @@ impl fmt::Display for Rule {
         write!(f, "{} ({})", self.title, groups)
     }
 }
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TieBreaker {
+    Quality,
+    Size,
+    Version,
+}
+
+impl TieBreaker {
+    pub const ALL: [TieBreaker; 3] = [TieBreaker::Quality, TieBreaker::Size, TieBreaker::Version];
+
+    fn default_chain() -> Vec<TieBreaker> {
+        vec![TieBreaker::Version, TieBreaker::Quality, TieBreaker::Size]
+    }
+
+    fn read_chain(names: &StringVec) -> Vec<TieBreaker> {
+        let mut chain = Vec::new();
+        for name in names {
+            match Self::ALL.into_iter().find(|t| t.to_string() == *name) {
+                Some(tie_breaker) => chain.push(tie_breaker),
+                None => log::warn!("Unknown tie-breaker '{}'", name),
+            }
+        }
+        chain
+    }
+}
+
+impl fmt::Display for TieBreaker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TieBreaker::Quality => "quality",
+            TieBreaker::Size => "size",
+            TieBreaker::Version => "version",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug)]
+pub enum FilterReason {
+    Excluded(EpisodeNumber),
+    GroupRange(EpisodeNumber, String),
+    MaximumEpisode(EpisodeNumber, i64),
+    MaximumVersion(i64, i64),
+    MinimumEpisode(EpisodeNumber, i64),
+    MinimumVersion(i64, i64),
+}
+
+impl fmt::Display for FilterReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilterReason::Excluded(episode) => write!(f, "Episode number {} is excluded", episode),
+            FilterReason::GroupRange(episode, group) => write!(
+                f,
+                "Episode number {} is outside the episode range of group \"{}\"",
+                episode, group
+            ),
+            FilterReason::MaximumEpisode(episode, maximum) => write!(
+                f,
+                "Episode number {} exceeds maximum of {}",
+                episode, maximum
+            ),
+            FilterReason::MaximumVersion(version, maximum) => {
+                write!(f, "Version {} exceeds maximum of {}", version, maximum)
+            }
+            FilterReason::MinimumEpisode(episode, minimum) => write!(
+                f,
+                "Episode number {} does not meet minimum of {}",
+                episode, minimum
+            ),
+            FilterReason::MinimumVersion(version, minimum) => write!(
+                f,
+                "Version {} does not meet minimum of {}",
+                version, minimum
+            ),
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct RuleMinimum {
     pub episode_number: Option<i64>,
+    pub score: Option<i64>,
+    pub version: Option<i64>,
 }
 
 impl RuleMinimum {
     fn read(hash: &yaml::Hash) -> Self {
         let mut episode_number: Option<i64> = None;
+        let mut score: Option<i64> = None;
+        let mut version: Option<i64> = None;
         for (key, value) in hash {
             match (key.as_str(), value) {
-                (Some("episode"), Yaml::Integer(integer)) => {
+                (Some(keys::EPISODE), Yaml::Integer(integer)) => {
                     episode_number = Some(*integer);
                 }
+                (Some(keys::SCORE), Yaml::Integer(integer)) => {
+                    score = Some(*integer);
+                }
+                (Some(keys::VERSION), Yaml::Integer(integer)) => {
+                    version = Some(*integer);
+                }
                 _ => (),
             }
         }
-        Self { episode_number }
+        Self {
+            episode_number,
+            score,
+            version,
+        }
     }
 }
 
+#[derive(Debug, Default)]
+pub struct RuleMaximum {
+    pub episode_number: Option<i64>,
+    pub version: Option<i64>,
+}
+
+impl RuleMaximum {
+    fn read(hash: &yaml::Hash) -> Self {
+        let mut episode_number: Option<i64> = None;
+        let mut version: Option<i64> = None;
+        for (key, value) in hash {
+            match (key.as_str(), value) {
+                (Some(keys::EPISODE), Yaml::Integer(integer)) => {
+                    episode_number = Some(*integer);
+                }
+                (Some(keys::VERSION), Yaml::Integer(integer)) => {
+                    version = Some(*integer);
+                }
+                _ => (),
+            }
+        }
+        Self {
+            episode_number,
+            version,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RuleFilter {
+    pub include: Vec<i64>,
+    pub exclude: Vec<i64>,
+    groups: HashMap<String, EpisodeRange>,
+}
+
+impl RuleFilter {
+    fn read(hash: &yaml::Hash) -> Self {
+        let mut filter = Self::default();
+        let integers = |array: &[Yaml]| array.iter().filter_map(Yaml::as_i64).collect();
+        for (key, value) in hash {
+            match (key.as_str(), value) {
+                (Some(keys::INCLUDE), Yaml::Array(array)) => {
+                    filter.include = integers(array);
+                }
+                (Some(keys::EXCLUDE), Yaml::Array(array)) => {
+                    filter.exclude = integers(array);
+                }
+                (Some(keys::GROUPS), Yaml::Hash(groups)) => {
+                    for (group, range) in groups {
+                        let (Some(group), Some(range)) = (group.as_str(), range.as_str()) else {
+                            continue;
+                        };
+                        match parse_episode_range(range) {
+                            Some(range) => {
+                                filter.groups.insert(normalize_group(group), range);
+                            }
+                            None => log::warn!("Could not parse episode range '{}'", range),
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        filter
+    }
+}
+
+// Parser options that are only set if they are present in the configuration, so that
+// rules can override individual options of the top-level parser configuration.
+#[derive(Debug, Default)]
+struct ParserOverrides {
+    allowed_delimiters: Option<Vec<char>>,
+    ignored_strings: Option<StringVec>,
+    parse_episode_number: Option<bool>,
+    parse_episode_title: Option<bool>,
+    parse_file_extension: Option<bool>,
+    parse_release_group: Option<bool>,
+    strip: Option<StringVec>,
+}
+
+impl ParserOverrides {
+    fn read(hash: &yaml::Hash) -> Self {
+        let mut overrides = Self::default();
+        for (key, value) in hash {
+            match (key.as_str(), value) {
+                (Some(keys::ALLOWED_DELIMITERS), Yaml::String(delimiters)) => {
+                    overrides.allowed_delimiters = Some(delimiters.chars().collect());
+                }
+                (Some(keys::IGNORED_STRINGS), Yaml::Array(array)) => {
+                    overrides.ignored_strings = StringVec::yaml_array_to_vec(array);
+                }
+                (Some(keys::PARSE_EPISODE_NUMBER), Yaml::Boolean(value)) => {
+                    overrides.parse_episode_number = Some(*value);
+                }
+                (Some(keys::PARSE_EPISODE_TITLE), Yaml::Boolean(value)) => {
+                    overrides.parse_episode_title = Some(*value);
+                }
+                (Some(keys::PARSE_FILE_EXTENSION), Yaml::Boolean(value)) => {
+                    overrides.parse_file_extension = Some(*value);
+                }
+                (Some(keys::PARSE_RELEASE_GROUP), Yaml::Boolean(value)) => {
+                    overrides.parse_release_group = Some(*value);
+                }
+                (Some(keys::STRIP), Yaml::Array(array)) => {
+                    overrides.strip = StringVec::yaml_array_to_vec(array);
+                }
+                (Some(key), _) => {
+                    log::warn!("Unknown parser option '{}'", key);
+                }
+                _ => (),
+            }
+        }
+        overrides
+    }
+
+    fn apply(&self, parser: &mut Parser) {
+        if let Some(delimiters) = &self.allowed_delimiters {
+            parser.options.allow_delimiters(delimiters);
+        }
+        if let Some(strings) = &self.ignored_strings {
+            parser.options.ignore_strings(strings);
+        }
+        if let Some(parse) = self.parse_episode_number {
+            parser.options.parse_episode_number(parse);
+        }
+        if let Some(parse) = self.parse_episode_title {
+            parser.options.parse_episode_title(parse);
+        }
+        if let Some(parse) = self.parse_file_extension {
+            parser.options.parse_file_extension(parse);
+        }
+        if let Some(parse) = self.parse_release_group {
+            parser.options.parse_release_group(parse);
+        }
+        if let Some(strip) = &self.strip {
+            parser.strip = strip.clone();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Sidecars {
+    pub extensions: StringVec,
+    pub directories: StringVec,
+}
+
+impl Default for Sidecars {
+    fn default() -> Self {
+        let extensions = ["ass", "ssa", "srt", "vtt", "sub", "idx", "sup", "xml"];
+        Self {
+            extensions: extensions.iter().map(|e| e.to_string()).collect(),
+            directories: vec!["Fonts".to_string()],
+        }
+    }
+}
+
+impl Sidecars {
+    fn read(hash: &yaml::Hash) -> Self {
+        let mut sidecars = Self::default();
+        for (key, value) in hash {
+            match (key.as_str(), value) {
+                (Some(keys::EXTENSIONS), Yaml::Array(array)) => {
+                    if let Some(vec) = StringVec::yaml_array_to_vec(array) {
+                        sidecars.extensions = vec;
+                    }
+                }
+                (Some(keys::DIRECTORIES), Yaml::Array(array)) => {
+                    if let Some(vec) = StringVec::yaml_array_to_vec(array) {
+                        sidecars.directories = vec;
+                    }
+                }
+                _ => (),
+            }
+        }
+        sidecars
+    }
+}
+
+#[derive(Debug)]
+pub struct Media {
+    pub extensions: StringVec,
+    pub sniff: bool,
+    pub skip_samples: bool,
+}
+
+impl Default for Media {
+    fn default() -> Self {
+        let extensions = [
+            "mkv", "mp4", "m4v", "avi", "webm", "ts", "m2ts", "ogm", "wmv",
+        ];
+        Self {
+            extensions: extensions.iter().map(|e| e.to_string()).collect(),
+            sniff: false,
+            skip_samples: true,
+        }
+    }
+}
+
+impl Media {
+    fn read(hash: &yaml::Hash) -> Self {
+        let mut media = Self::default();
+        for (key, value) in hash {
+            match (key.as_str(), value) {
+                (Some(keys::EXTENSIONS), Yaml::Array(array)) => {
+                    if let Some(vec) = StringVec::yaml_array_to_vec(array) {
+                        media.extensions = vec;
+                    }
+                }
+                (Some(keys::SNIFF), Yaml::Boolean(value)) => {
+                    media.sniff = *value;
+                }
+                (Some(keys::SKIP_SAMPLES), Yaml::Boolean(value)) => {
+                    media.skip_samples = *value;
+                }
+                _ => (),
+            }
+        }
+        media
+    }
+}
+
+type EpisodeRange = (Option<i64>, Option<i64>);
+
+// Parses ranges such as "1-12" and "13-".
+fn parse_episode_range(range: &str) -> Option<EpisodeRange> {
+    let (first, last) = range.split_once('-')?;
+    let parse = |value: &str| -> Option<Option<i64>> {
+        let value = value.trim();
+        if value.is_empty() {
+            Some(None)
+        } else {
+            value.parse().ok().map(Some)
+        }
+    };
+    Some((parse(first)?, parse(last)?))
+}
+
+// Restricts a rule to a range of episodes, a season or a year, which makes it possible
+// for several rules to share the same title or alias.
+#[derive(Debug, Default)]
+pub struct RuleScope {
+    first_episode: Option<i64>,
+    last_episode: Option<i64>,
+    season: Option<i64>,
+    year: Option<i64>,
+}
+
+impl RuleScope {
+    fn read(config: &yaml::Hash) -> Self {
+        let mut scope = Self::default();
+        for (key, value) in config {
+            match (key.as_str(), value) {
+                (Some(keys::EPISODES), Yaml::Integer(episode)) => {
+                    scope.first_episode = Some(*episode);
+                    scope.last_episode = Some(*episode);
+                }
+                (Some(keys::EPISODES), Yaml::String(range)) => {
+                    let Some((first, last)) = parse_episode_range(range) else {
+                        log::warn!("Could not parse episode range '{}'", range);
+                        continue;
+                    };
+                    scope.first_episode = first;
+                    scope.last_episode = last;
+                }
+                (Some(keys::SEASON), Yaml::Integer(season)) => {
+                    scope.season = Some(*season);
+                }
+                (Some(keys::YEAR), Yaml::Integer(year)) => {
+                    scope.year = Some(*year);
+                }
+                _ => (),
+            }
+        }
+        scope
+    }
+
+    pub fn contains(&self, release: &Release) -> bool {
+        if self.first_episode.is_some() || self.last_episode.is_some() {
+            let Some(episode) = &release.episode else {
+                return false;
+            };
+            if self
+                .first_episode
+                .is_some_and(|first| episode.is_below(first))
+                || self.last_episode.is_some_and(|last| episode.is_above(last))
+            {
+                return false;
+            }
+        }
+        if let Some(season) = self.season {
+            if release.season_or_first() != season {
+                return false;
+            }
+        }
+        // Releases without a year can't be excluded based on it.
+        if let (Some(year), Some(release_year)) = (self.year, release.year) {
+            if i64::from(release_year) != year {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RuleTypes {
+    // All types are accepted unless the accepted types are listed.
+    accept: Option<Vec<EpisodeType>>,
+    folders: Vec<(EpisodeType, String)>,
+}
+
+impl RuleTypes {
+    fn read(hash: &yaml::Hash) -> Self {
+        let mut types = Self::default();
+        for (key, value) in hash {
+            match (key.as_str(), value) {
+                (Some(keys::ACCEPT), Yaml::Array(array)) => {
+                    let names = StringVec::yaml_array_to_vec(array).unwrap_or_default();
+                    types.accept = Some(names.iter().filter_map(|n| Self::parse(n)).collect());
+                }
+                (Some(keys::FOLDERS), Yaml::Hash(folders)) => {
+                    for (name, folder) in folders {
+                        let (Some(name), Some(folder)) = (name.as_str(), folder.as_str()) else {
+                            continue;
+                        };
+                        if let Some(episode_type) = Self::parse(name) {
+                            types.folders.push((episode_type, folder.to_string()));
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        types
+    }
+
+    fn parse(name: &str) -> Option<EpisodeType> {
+        let episode_type = EpisodeType::from_name(name);
+        if episode_type.is_none() {
+            log::warn!("Unknown episode type '{}'", name);
+        }
+        episode_type
+    }
+
+    pub fn accepts(&self, episode_type: &EpisodeType) -> bool {
+        match &self.accept {
+            Some(accept) => accept.contains(episode_type),
+            None => true,
+        }
+    }
+
+    pub fn get_folder(&self, episode_type: &EpisodeType) -> Option<&str> {
+        self.folders
+            .iter()
+            .find(|(folder_type, _)| folder_type == episode_type)
+            .map(|(_, folder)| folder.as_str())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RuleQuality {
+    // Preferences are compared in the order they are listed in the configuration.
+    preferences: Vec<(QualityCategory, StringVec)>,
+    before_group: Vec<QualityCategory>,
+}
+
+impl RuleQuality {
+    fn read(hash: &yaml::Hash) -> Self {
+        let mut quality = Self::default();
+        for (key, value) in hash {
+            let Some(key) = key.as_str() else {
+                continue;
+            };
+            let Some(array) = value.as_vec() else {
+                log::warn!("Quality preference '{}' must be a list", key);
+                continue;
+            };
+            let Some(vec) = StringVec::yaml_array_to_vec(array) else {
+                continue;
+            };
+            if key == keys::BEFORE_GROUP {
+                for name in vec {
+                    match QualityCategory::from_name(&name) {
+                        Some(category) => quality.before_group.push(category),
+                        None => log::warn!("Unknown quality category '{}'", name),
+                    }
+                }
+                continue;
+            }
+            // Unknown categories are reported by the configuration check.
+            let Some(category) = QualityCategory::from_name(key) else {
+                continue;
+            };
+            let values = vec.iter().map(|v| category.canonicalize(v)).collect();
+            quality.preferences.push((category, values));
+        }
+        quality
+    }
+
+    // Ranks the quality against the preferences (lower is better). Values that are
+    // not listed rank below all listed values.
+    pub fn rank(&self, quality: &Quality, before_group: bool) -> Vec<usize> {
+        self.preferences
+            .iter()
+            .filter(|(category, _)| self.before_group.contains(category) == before_group)
+            .map(|(category, values)| {
+                let terms = quality.get(*category);
+                values
+                    .iter()
+                    .position(|value| terms.contains(&value.as_str()))
+                    .unwrap_or(values.len())
+            })
+            .collect()
+    }
+}
+
+// Expands `${VAR}` and `${VAR:-default}` references and a leading `~` in a path.
+fn expand_path(value: &str) -> PathBuf {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+            break;
+        };
+        expanded.push_str(&rest[..start]);
+        let reference = &rest[start + 2..end];
+        let (name, default) = match reference.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (reference, None),
+        };
+        // Like in shells, the default is also used when the variable is set but empty.
+        match (env::var(name).ok().filter(|v| !v.is_empty()), default) {
+            (Some(variable), _) => expanded.push_str(&variable),
+            (None, Some(default)) => expanded.push_str(default),
+            (None, None) => log::warn!("Environment variable '{}' is not set or empty", name),
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+
+    let home = env::var_os("HOME").map(PathBuf::from);
+    match (expanded.strip_prefix('~'), home) {
+        (Some(""), Some(home)) => home,
+        (Some(path), Some(home)) if path.starts_with('/') => home.join(&path[1..]),
+        _ => PathBuf::from(expanded),
+    }
+}
+
+// Files with an unknown extension are read as YAML.
+fn load_hash(path: &PathBuf) -> Result<yaml::Hash, Error> {
+    let file_content = match load_file_to_string(path) {
+        Ok(string) => string,
+        Err(e) => return Err(Error::Io(path.clone(), e)),
+    };
+    Format::from_path(path)
+        .unwrap_or(Format::Yaml)
+        .parse(&file_content)
+}
+
 fn load_file_to_string(path: &PathBuf) -> Result<String, io::Error> {
-    let mut file = File::open(&path)?;
+    let mut file = File::open(path)?;
     let mut file_content = String::new();
     file.read_to_string(&mut file_content)?;
     Ok(file_content)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_episode_ranges() {
+        assert_eq!(parse_episode_range("1-6"), Some((Some(1), Some(6))));
+        assert_eq!(parse_episode_range(" 1 - 6 "), Some((Some(1), Some(6))));
+        assert_eq!(parse_episode_range("13-"), Some((Some(13), None)));
+        assert_eq!(parse_episode_range("-12"), Some((None, Some(12))));
+        assert_eq!(parse_episode_range("12"), None);
+        assert_eq!(parse_episode_range("a-b"), None);
+        assert_eq!(parse_episode_range("1-6-8"), None);
+    }
+
+    #[test]
+    fn expands_variables() {
+        env::set_var("YURIZAKI_TEST_PATH", "/mnt/anime");
+        env::set_var("YURIZAKI_TEST_EMPTY", "");
+        env::remove_var("YURIZAKI_TEST_UNSET");
+        let expand = expand_path;
+        assert_eq!(
+            expand("${YURIZAKI_TEST_PATH}/library"),
+            PathBuf::from("/mnt/anime/library")
+        );
+        assert_eq!(
+            expand("${YURIZAKI_TEST_UNSET:-/default}/x"),
+            PathBuf::from("/default/x")
+        );
+        assert_eq!(
+            expand("${YURIZAKI_TEST_EMPTY:-/default}"),
+            PathBuf::from("/default")
+        );
+        assert_eq!(
+            expand("${YURIZAKI_TEST_PATH:-/default}"),
+            PathBuf::from("/mnt/anime")
+        );
+        assert_eq!(expand("${YURIZAKI_TEST_UNSET}/x"), PathBuf::from("/x"));
+        assert_eq!(
+            expand("/a/${YURIZAKI_TEST_PATH"),
+            PathBuf::from("/a/${YURIZAKI_TEST_PATH")
+        );
+        assert_eq!(
+            expand("$YURIZAKI_TEST_PATH/x"),
+            PathBuf::from("$YURIZAKI_TEST_PATH/x")
+        );
+    }
+
+    #[test]
+    fn expands_home() {
+        let Some(home) = env::var_os("HOME").map(PathBuf::from) else {
+            return;
+        };
+        assert_eq!(expand_path("~"), home);
+        assert_eq!(expand_path("~/anime"), home.join("anime"));
+        assert_eq!(expand_path("~user/anime"), PathBuf::from("~user/anime"));
+        assert_eq!(expand_path("/anime/~"), PathBuf::from("/anime/~"));
+    }
+
+    #[test]
+    fn scopes_releases() {
+        let scope = |source: &str| {
+            let yaml = yaml_rust::YamlLoader::load_from_str(source).unwrap();
+            RuleScope::read(yaml[0].as_hash().unwrap())
+        };
+        let parser = Parser::default();
+        let release = |filename: &str| Release::from(filename, &parser).unwrap();
+        let episode = release("[Group] Title - 05.mkv");
+        let second_season = release("[Group] Title S2 - 05.mkv");
+        let movie = release("[Group] Title.mkv");
+        let dated = release("[Group] Title (2020) - 05.mkv");
+
+        assert!(scope("{}").contains(&movie));
+        assert!(scope("episodes: 1-12").contains(&episode));
+        assert!(scope("episodes: 5").contains(&episode));
+        assert!(!scope("episodes: 13-").contains(&episode));
+        assert!(!scope("episodes: -4").contains(&episode));
+        assert!(!scope("episodes: 1-12").contains(&movie));
+        assert!(scope("season: 1").contains(&episode));
+        assert!(!scope("season: 2").contains(&episode));
+        assert!(scope("season: 2").contains(&second_season));
+        assert!(scope("year: 2020").contains(&dated));
+        assert!(!scope("year: 2021").contains(&dated));
+        assert!(scope("year: 2021").contains(&episode));
+    }
+}
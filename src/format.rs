@@ -0,0 +1,110 @@
+use std::path::Path;
+
+use yaml_rust::{yaml, YamlLoader};
+
+#[cfg(any(feature = "toml", feature = "json"))]
+use yaml_rust::Yaml;
+
+use crate::config::Error;
+
+// Configuration files can be written in any of these formats. TOML and JSON documents are
+// converted into YAML values so that every format shares the same configuration reader.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Yaml,
+    #[cfg(feature = "toml")]
+    Toml,
+    #[cfg(feature = "json")]
+    Json,
+}
+
+impl Format {
+    pub const ALL: &'static [Format] = &[
+        Format::Yaml,
+        #[cfg(feature = "toml")]
+        Format::Toml,
+        #[cfg(feature = "json")]
+        Format::Json,
+    ];
+
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Format::Yaml => &["yml", "yaml"],
+            #[cfg(feature = "toml")]
+            Format::Toml => &["toml"],
+            #[cfg(feature = "json")]
+            Format::Json => &["json"],
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        Self::ALL
+            .iter()
+            .find(|format| format.extensions().contains(&extension.as_str()))
+            .copied()
+    }
+
+    // Parses a document into the top-level dictionary of the configuration.
+    pub fn parse(&self, content: &str) -> Result<yaml::Hash, Error> {
+        let document = match self {
+            Format::Yaml => match YamlLoader::load_from_str(content) {
+                Ok(documents) => documents.into_iter().next().ok_or(Error::Yaml)?,
+                Err(_) => return Err(Error::Yaml),
+            },
+            #[cfg(feature = "toml")]
+            Format::Toml => match content.parse::<toml::Table>() {
+                Ok(table) => from_toml(toml::Value::Table(table)),
+                Err(_) => return Err(Error::Toml),
+            },
+            #[cfg(feature = "json")]
+            Format::Json => match serde_json::from_str(content) {
+                Ok(value) => from_json(value),
+                Err(_) => return Err(Error::Json),
+            },
+        };
+        Ok(document.into_hash().unwrap_or_default())
+    }
+}
+
+pub fn is_config_file(path: &Path) -> bool {
+    Format::from_path(path).is_some()
+}
+
+#[cfg(feature = "toml")]
+fn from_toml(value: toml::Value) -> Yaml {
+    match value {
+        toml::Value::String(string) => Yaml::String(string),
+        toml::Value::Integer(integer) => Yaml::Integer(integer),
+        toml::Value::Float(float) => Yaml::Real(float.to_string()),
+        toml::Value::Boolean(boolean) => Yaml::Boolean(boolean),
+        toml::Value::Datetime(datetime) => Yaml::String(datetime.to_string()),
+        toml::Value::Array(array) => Yaml::Array(array.into_iter().map(from_toml).collect()),
+        toml::Value::Table(table) => Yaml::Hash(
+            table
+                .into_iter()
+                .map(|(key, value)| (Yaml::String(key), from_toml(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(feature = "json")]
+fn from_json(value: serde_json::Value) -> Yaml {
+    match value {
+        serde_json::Value::Null => Yaml::Null,
+        serde_json::Value::Bool(boolean) => Yaml::Boolean(boolean),
+        serde_json::Value::Number(number) => match number.as_i64() {
+            Some(integer) => Yaml::Integer(integer),
+            None => Yaml::Real(number.to_string()),
+        },
+        serde_json::Value::String(string) => Yaml::String(string),
+        serde_json::Value::Array(array) => Yaml::Array(array.into_iter().map(from_json).collect()),
+        serde_json::Value::Object(object) => Yaml::Hash(
+            object
+                .into_iter()
+                .map(|(key, value)| (Yaml::String(key), from_json(value)))
+                .collect(),
+        ),
+    }
+}
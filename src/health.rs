@@ -0,0 +1,91 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::config::Configuration;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+const MAXIMUM_BACKOFF: Duration = Duration::from_secs(300);
+
+#[derive(Debug, PartialEq)]
+pub enum Unavailable {
+    Source(PathBuf),
+    Library(PathBuf),
+}
+
+impl fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Unavailable::Source(path) => {
+                write!(f, "source path \"{}\" is not available", path.display())
+            }
+            Unavailable::Library(path) => {
+                write!(f, "library path \"{}\" is not available", path.display())
+            }
+        }
+    }
+}
+
+fn is_available(path: &Path) -> bool {
+    path.read_dir().is_ok()
+}
+
+// The library root is never created, since a missing root usually means that the volume
+// isn't mounted and files would end up on the disk below the mount point.
+pub fn check(config: &Configuration) -> Result<(), Unavailable> {
+    if !is_available(&config.source) {
+        return Err(Unavailable::Source(config.source.clone()));
+    }
+    if !is_available(&config.library) {
+        return Err(Unavailable::Library(config.library.clone()));
+    }
+    Ok(())
+}
+
+// The paths are checked periodically, since a disappearing volume doesn't necessarily
+// generate any events. Processing is paused while the source or library is unavailable,
+// and the paths are checked again with an exponential backoff.
+pub struct Monitor {
+    backoff: Option<Duration>,
+    next_check: Instant,
+}
+
+impl Monitor {
+    pub fn new() -> Self {
+        Monitor {
+            backoff: None,
+            next_check: Instant::now() + CHECK_INTERVAL,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.backoff.is_some()
+    }
+
+    pub fn pause(&mut self, reason: &Unavailable) {
+        let backoff = match self.backoff {
+            Some(backoff) => (backoff * 2).min(MAXIMUM_BACKOFF),
+            None => {
+                log::warn!("PAUSED: {}", reason);
+                INITIAL_BACKOFF
+            }
+        };
+        log::debug!("Checking again in {} seconds", backoff.as_secs());
+        self.backoff = Some(backoff);
+        self.next_check = Instant::now() + backoff;
+    }
+
+    pub fn resume(&mut self) {
+        if self.is_paused() {
+            log::info!("RESUMED: source and library paths are available again");
+        }
+        self.backoff = None;
+        self.next_check = Instant::now() + CHECK_INTERVAL;
+    }
+
+    // Time left until the next check.
+    pub fn timeout(&self) -> Duration {
+        self.next_check.saturating_duration_since(Instant::now())
+    }
+}
@@ -2,19 +2,32 @@
 extern crate trash;
 
 use log::{debug, error, info, warn, LevelFilter};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::env;
 use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
-use std::sync::mpsc::channel;
+use std::ptr;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use notify::{watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
 use simple_logger::SimpleLogger;
 
 mod anime;
 mod config;
+mod format;
+mod health;
+mod media;
+mod reconcile;
+mod schema;
+mod sidecar;
+mod title;
+mod watch;
 
 #[derive(Debug)]
 enum ActionCategory {
@@ -28,6 +41,13 @@ struct Action {
     path: PathBuf,
 }
 
+#[derive(PartialEq)]
+enum Command {
+    Watch,
+    Reconcile,
+    Schema,
+}
+
 enum ExistingRelease {
     Inferior(PathBuf),
     Superior(PathBuf),
@@ -43,88 +63,269 @@ fn entry_to_file_path(entry: io::Result<fs::DirEntry>) -> Option<PathBuf> {
 }
 
 fn scan_directory(config: &config::Configuration) {
-    for entry in fs::read_dir(&config.source).unwrap() {
-        if let Some(path) = entry_to_file_path(entry) {
-            handle_file(&config, path);
+    let entries = match fs::read_dir(&config.source) {
+        Ok(entries) => entries,
+        Err(error) => {
+            error!(
+                "Unable to read source path \"{}\" ({})",
+                config.source.display(),
+                error
+            );
+            return;
         }
+    };
+    for entry in entries {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        handle_path(config, entry.path());
     }
 }
 
-fn find_existing_release(
-    path: &PathBuf,
-    release: anime::Release,
-    rule: &config::Rule,
+fn make_directory_release(config: &config::Configuration, path: &Path) -> Option<anime::Release> {
+    let dirname = path.file_name()?.to_str()?;
+    let Some(mut release) = anime::Release::from(dirname, &config.parser) else {
+        debug!("Could not parse directory \"{}\" as a release", dirname);
+        return None;
+    };
+    // The type isn't inherited from the directory name, since packs often contain both
+    // episodes and specials. Subfolders of the directory may set it instead.
+    release.episode_type = anime::EpisodeType::Episode;
+    release.anime_type = None;
+    Some(release)
+}
+
+// Parses the release that a file inside a release directory inherits from, including the
+// subfolders between the directory and the file.
+fn make_parent_release(
     config: &config::Configuration,
-) -> Option<ExistingRelease> {
-    let group_priority = rule.get_priority(&release.group).unwrap();
-    let episode_number = release.numerical_episode();
+    directory: &Path,
+    path: &Path,
+) -> Option<anime::Release> {
+    let mut parent = make_directory_release(config, directory)?;
+    let folders = path.parent()?.strip_prefix(directory).ok()?;
+    for folder in folders.iter().filter_map(|folder| folder.to_str()) {
+        parent = anime::Release::from_folder(folder, &parent, &config.parser);
+    }
+    Some(parent)
+}
 
-    for entry in fs::read_dir(path).unwrap() {
-        let Some(path) = entry_to_file_path(entry) else {
+fn handle_path(config: &config::Configuration, path: PathBuf) -> Option<()> {
+    // Anything nested inside the source directory belongs to a release directory, and
+    // the top-level directory name provides the defaults for the contained files.
+    let relative = path.strip_prefix(&config.source).ok()?;
+    let top_level = config.source.join(relative.components().next()?);
+    if is_sidecar_directory(config, &top_level) {
+        return None;
+    }
+    if top_level == path {
+        if path.is_dir() {
+            return handle_directory(config, &path);
+        }
+        return handle_file(config, path, None);
+    }
+    if !path.is_file() {
+        return None;
+    }
+    handle_file(config, path, Some(&top_level))
+}
+
+fn handle_directory(config: &config::Configuration, path: &Path) -> Option<()> {
+    let parent = make_directory_release(config, path);
+    if let Some(parent) = &parent {
+        info!(
+            "DIRECTORY: \"{}\" => {} ({})",
+            path.display(),
+            parent.title,
+            parent.group
+        );
+    }
+    let mut directories = vec![path.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let Ok(entries) = fs::read_dir(&directory) else {
+            warn!("Unable to read directory \"{}\"", directory.display());
             continue;
         };
+        for entry in entries {
+            let Ok(entry) = entry else {
+                continue;
+            };
+            let entry_path = entry.path();
+            if entry_path.is_dir() {
+                // Sidecar directories are copied along with the videos.
+                if !is_sidecar_directory(config, &entry_path) {
+                    directories.push(entry_path);
+                }
+            } else {
+                handle_file(config, entry_path, Some(path));
+            }
+        }
+    }
+    Some(())
+}
+
+fn is_sidecar_directory(config: &config::Configuration, path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    config.sidecars.directories.iter().any(|d| d == name)
+}
+
+fn is_same_episode(release: &anime::Release, other: &anime::Release) -> bool {
+    // Check that the releases are of the same type, since we wouldn't want to match
+    // "Anime - OVA1" to "Anime - 01".
+    if release.episode_type != other.episode_type {
+        return false;
+    }
+
+    // Check that the releases are from the same season.
+    if release.season_or_first() != other.season_or_first() {
+        return false;
+    }
+
+    match (&release.episode, &other.episode) {
+        (Some(episode), Some(other_episode)) => episode == other_episode,
+        (Some(_), None) | (None, Some(_)) => false,
+        // Releases without an episode number (such as movies) are only the same
+        // release if they have the same title.
+        (None, None) => title::normalize(&release.title) == title::normalize(&other.title),
+    }
+}
+
+// Compares two releases of the same episode, where Greater means that the first release is
+// superior. Quality preferences listed in `before_group` take precedence over the release
+// group score, and ties are resolved with the tie-breakers of the rule. Releases from
+// groups that aren't listed in the rule can't be compared.
+fn compare_releases(
+    rule: &config::Rule,
+    release: &anime::Release,
+    path: &Path,
+    other: &anime::Release,
+    other_path: &Path,
+) -> Option<Ordering> {
+    let group_score = rule.get_score(&release.group)?;
+    let other_group_score = rule.get_score(&other.group)?;
+    let quality_ordering = rule
+        .quality
+        .rank(&other.quality, true)
+        .cmp(&rule.quality.rank(&release.quality, true));
+    let mut ordering = quality_ordering.then(group_score.cmp(&other_group_score));
+    for tie_breaker in &rule.tie_breakers {
+        ordering = ordering.then_with(|| match tie_breaker {
+            // Greater version is superior.
+            config::TieBreaker::Version => release.version.cmp(&other.version),
+            config::TieBreaker::Quality => rule
+                .quality
+                .rank(&other.quality, false)
+                .cmp(&rule.quality.rank(&release.quality, false)),
+            // Larger file is superior.
+            config::TieBreaker::Size => get_filesize(path).cmp(&get_filesize(other_path)),
+        });
+    }
+    Some(ordering)
+}
+
+// Lists the videos of a library directory. Release directories are kept in the library, so
+// videos inside them are returned with the release parsed from the directory name. Other
+// subdirectories (such as season folders) are only searched when `recursive` is set.
+fn find_library_videos(
+    config: &config::Configuration,
+    directory: &Path,
+    recursive: bool,
+) -> io::Result<Vec<(PathBuf, Option<anime::Release>)>> {
+    let mut videos = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            // Only videos are compared; sidecars and other files are ignored.
+            if media::has_media_extension(&config.media, &path) {
+                videos.push((path, None));
+            }
+            continue;
+        }
+        if is_sidecar_directory(config, &path) {
+            continue;
+        }
+        if make_directory_release(config, &path).is_some() {
+            find_release_videos(config, &path, &path, &mut videos)?;
+        } else if recursive {
+            videos.extend(find_library_videos(config, &path, true)?);
+        }
+    }
+    Ok(videos)
+}
+
+// Collects the videos of a release directory in the library, including its subfolders.
+fn find_release_videos(
+    config: &config::Configuration,
+    release_directory: &Path,
+    directory: &Path,
+    videos: &mut Vec<(PathBuf, Option<anime::Release>)>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if !is_sidecar_directory(config, &path) {
+                find_release_videos(config, release_directory, &path, videos)?;
+            }
+        } else if media::has_media_extension(&config.media, &path) {
+            let parent = make_parent_release(config, release_directory, &path);
+            videos.push((path, parent));
+        }
+    }
+    Ok(())
+}
+
+fn find_existing_release(
+    path: &Path,
+    source_path: &Path,
+    release: anime::Release,
+    rule: &config::Rule,
+    config: &config::Configuration,
+) -> io::Result<Option<ExistingRelease>> {
+    for (path, parent) in find_library_videos(config, path, false)? {
         let Some(filename) = path.file_name() else {
             continue;
         };
         let Some(filename) = filename.to_str() else {
             continue;
         };
-        let Some(entry_release) = make_release(config, filename) else {
+        let Some(entry_release) = make_release(config, filename, parent.as_ref(), Some(rule))
+        else {
             continue;
         };
 
-        // Check that the entry is of the same type as the given release, since we
-        // wouldn't want to match "Anime - OVA1" to "Anime - 01".
-        if entry_release.episode_type != release.episode_type {
+        if !is_same_episode(&release, &entry_release) {
             continue;
         }
 
-        // Check that entry is the same episode as the given release.
-        match (episode_number, entry_release.numerical_episode()) {
-            (Some(episode_number), Some(entry_episode_number)) => {
-                if entry_episode_number != episode_number {
-                    continue;
-                }
-            }
-            (Some(_), None) | (None, Some(_)) => continue,
-            (None, None) => {
-                if entry_release.episode != release.episode {
-                    continue;
-                }
-            }
+        // Same checksum means that the entry is the same release under a different name.
+        if release.checksum.is_some() && release.checksum == entry_release.checksum {
+            return Ok(Some(ExistingRelease::Superior(path)));
         }
 
-        // Check if library contains a release from a group with a higher priority.
-        let entry_group_priority = match rule.get_priority(&entry_release.group) {
-            Some(position) => position,
-            None => continue,
+        let Some(ordering) = compare_releases(rule, &release, source_path, &entry_release, &path)
+        else {
+            continue;
         };
-        if entry_group_priority < group_priority {
-            // Entry's release group is listed higher (has lower index).
-            return Some(ExistingRelease::Superior(path));
-        } else if entry_group_priority > group_priority {
-            // Entry's release group is listed lower (has higher index).
-            return Some(ExistingRelease::Inferior(path));
-        } else if release.version < entry_release.version {
-            // Entry is a greater version.
-            return Some(ExistingRelease::Superior(path));
-        } else if release.version > entry_release.version {
-            // Entry is a lesser version.
-            return Some(ExistingRelease::Inferior(path));
+        match ordering {
+            Ordering::Less => return Ok(Some(ExistingRelease::Superior(path))),
+            Ordering::Greater => return Ok(Some(ExistingRelease::Inferior(path))),
+            Ordering::Equal => {}
         }
         // If the control flow reaches here, we have the same episode for the same group
-        // priority and for the same release version. Either we are dealing with the same
-        // releases or there's a need for additional checks.
+        // score and all tie-breakers are equal, so we are most likely dealing with the
+        // same release.
     }
-    None
+    Ok(None)
 }
 
-fn get_filesize(path: &PathBuf) -> Option<i64> {
+fn get_filesize(path: &Path) -> Option<i64> {
     let metadata = fs::metadata(path).ok()?;
     Some(metadata.len() as i64)
 }
 
-fn should_recopy(from: &PathBuf, to: &PathBuf) -> bool {
+fn should_recopy(from: &Path, to: &Path) -> bool {
     let Some(to_filesize) = get_filesize(to) else {
         // Failed to get filesize of destination file, so recopying just in case.
         return true;
@@ -171,33 +372,213 @@ fn remove_file(_config: &config::Configuration, path: &PathBuf) {
     }
 }
 
-fn make_release(config: &config::Configuration, filename: &str) -> Option<anime::Release> {
+fn remove_release(config: &config::Configuration, path: &PathBuf) {
+    // Sidecars are removed first so that no orphaned subtitles are left behind even if
+    // removing the video itself fails.
+    for sidecar_path in sidecar::find_sidecars(&config.sidecars, &config.media, path) {
+        remove_file(config, &sidecar_path);
+    }
+    remove_file(config, path);
+    // Release directories and their subfolders are removed once they are empty.
+    let release_directory = path
+        .ancestors()
+        .skip(1)
+        .take_while(|directory| {
+            directory.starts_with(&config.library) && *directory != config.library
+        })
+        .find(|directory| make_directory_release(config, directory).is_some());
+    let Some(release_directory) = release_directory else {
+        return;
+    };
+    for directory in path.ancestors().skip(1) {
+        if fs::remove_dir(directory).is_err() {
+            break;
+        }
+        debug!("Removed directory \"{}\"", directory.display());
+        if directory == release_directory {
+            break;
+        }
+    }
+}
+
+fn copy_sidecars(config: &config::Configuration, video: &Path, video_target: &Path) {
+    for sidecar_path in sidecar::find_sidecars(&config.sidecars, &config.media, video) {
+        let Some(target) = sidecar::target_path(video, video_target, &sidecar_path) else {
+            continue;
+        };
+        if target.exists() && !should_recopy(&sidecar_path, &target) {
+            continue;
+        }
+        match fs::copy(&sidecar_path, &target) {
+            Ok(_) => {
+                info!(
+                    "Copied sidecar \"{}\" to \"{}\"",
+                    sidecar_path.display(),
+                    target.display()
+                );
+            }
+            Err(error) => {
+                error!(
+                    "Failed to copy sidecar \"{}\" ({})",
+                    sidecar_path.display(),
+                    error
+                );
+            }
+        }
+    }
+
+    let Some(target_directory) = video_target.parent() else {
+        return;
+    };
+    for directory in sidecar::find_directories(&config.sidecars, video) {
+        let Some(name) = directory.file_name() else {
+            continue;
+        };
+        copy_directory(&directory, &target_directory.join(name));
+    }
+}
+
+fn copy_directory(from: &Path, to: &Path) {
+    if let Err(error) = fs::create_dir_all(to) {
+        error!(
+            "Unable to create directory \"{}\" ({})",
+            to.display(),
+            error
+        );
+        return;
+    }
+    let Ok(entries) = fs::read_dir(from) else {
+        return;
+    };
+    for entry in entries {
+        let Some(path) = entry_to_file_path(entry) else {
+            continue;
+        };
+        let Some(filename) = path.file_name() else {
+            continue;
+        };
+        let target = to.join(filename);
+        if target.exists() && !should_recopy(&path, &target) {
+            continue;
+        }
+        match fs::copy(&path, &target) {
+            Ok(_) => debug!("Copied \"{}\" to \"{}\"", path.display(), target.display()),
+            Err(error) => error!("Failed to copy \"{}\" ({})", path.display(), error),
+        }
+    }
+}
+
+fn create_directory(path: &Path) -> bool {
+    if path.exists() {
+        return true;
+    }
+    debug!("Missing directory \"{}\", creating...", path.display());
+    match fs::create_dir_all(path) {
+        Ok(()) => {
+            debug!("Directory \"{}\" created", path.display());
+            true
+        }
+        Err(error) => {
+            error!(
+                "Unable to create directory \"{}\" ({}), skipping file...",
+                path.display(),
+                error
+            );
+            false
+        }
+    }
+}
+
+fn make_release(
+    config: &config::Configuration,
+    filename: &str,
+    parent: Option<&anime::Release>,
+    rule: Option<&config::Rule>,
+) -> Option<anime::Release> {
+    // Rules may override the top-level parser configuration.
+    let parser = rule
+        .and_then(|rule| rule.parser.as_ref())
+        .unwrap_or(&config.parser);
+
     #[cfg(feature = "regex")]
     for (regex, index) in &config.regexes {
         if let Some(captures) = regex.captures(filename) {
             debug!("Matched {} to regex {}", filename, regex);
             let rule = &config.rules[*index];
-            if let Some(release) = anime::Release::from_captures(&rule.title, captures) {
+            if let Some(release) = anime::Release::from_captures(&rule.title, captures, parser) {
                 return Some(release);
             }
         }
     }
 
-    anime::Release::from(filename)
+    // Global regexes capture the title as well, which is then resolved to a rule. A match
+    // whose title doesn't resolve to a rule is left to the next regex or to anitomy.
+    #[cfg(feature = "regex")]
+    for regex in &config.global_regexes {
+        let Some(captures) = regex.captures(filename) else {
+            continue;
+        };
+        let Some(title) = captures.name("title") else {
+            continue;
+        };
+        debug!("Matched {} to global regex {}", filename, regex);
+        let title = title.as_str().to_string();
+        let Some(release) = anime::Release::from_captures(&title, captures, parser) else {
+            continue;
+        };
+        if config.get_rule(&release).is_none() {
+            debug!("No rule for title {} of global regex {}", title, regex);
+            continue;
+        }
+        return Some(release);
+    }
+
+    match parent {
+        Some(parent) => anime::Release::from_parent(filename, parent, parser),
+        None => anime::Release::from(filename, parser),
+    }
 }
 
-fn handle_file(config: &config::Configuration, path: PathBuf) -> Option<()> {
+// Files of a release directory are parsed with the release of the directory name as their
+// parent, and the directory is kept in the library to parse them the same way later on.
+fn handle_file(
+    config: &config::Configuration,
+    path: PathBuf,
+    directory: Option<&Path>,
+) -> Option<()> {
     if !path.exists() {
         // File doesn't actually exist, so let's bail out.
         return None;
     }
 
+    if sidecar::is_sidecar(&config.sidecars, &path) {
+        // Sidecars are handled together with their video.
+        return None;
+    }
+
     let filename = &path.file_name()?.to_str()?;
-    let release = make_release(config, filename)?;
-    let rule = config.get_rule(&release.title)?;
+    if let Err(reason) = media::check(&config.media, &path) {
+        info!("SKIP: \"{}\" is not media ({})", filename, reason);
+        return None;
+    }
+    let parent = directory.and_then(|directory| make_parent_release(config, directory, &path));
+    let parent = parent.as_ref();
+    let release = make_release(config, filename, parent, None)?;
+    let rule = config.get_rule(&release)?;
+    // Parse again with the parser options of the rule, as long as the result still
+    // belongs to the same rule.
+    let release = match rule.parser {
+        Some(_) => match make_release(config, filename, parent, Some(rule)) {
+            Some(reparsed) if config.get_rule(&reparsed).is_some_and(|r| ptr::eq(r, rule)) => {
+                reparsed
+            }
+            _ => release,
+        },
+        None => release,
+    };
     info!("MATCH: \"{}\" => {}", &filename, rule.title);
 
-    if !rule.groups.contains(&release.group) {
+    if rule.get_score(&release.group).is_none() {
         info!(
             "SKIP: Group \"{}\" not listed in {}",
             release.group, rule.title
@@ -205,18 +586,40 @@ fn handle_file(config: &config::Configuration, path: PathBuf) -> Option<()> {
         return None;
     }
 
-    // Check minimum episode threshold.
-    match (rule.minimum.episode_number, release.numerical_episode()) {
-        (Some(minimum), Some(episode_number)) => {
-            if minimum > episode_number as i64 {
-                info!(
-                    "SKIP: Episode number {} does not meet minimum of {}",
-                    episode_number, minimum
-                );
-                return None;
-            }
+    // Check minimum group score threshold.
+    if let (Some(minimum), Some(score)) = (rule.minimum.score, rule.get_score(&release.group)) {
+        if minimum > score {
+            info!(
+                "SKIP: Group \"{}\" score {} does not meet minimum of {}",
+                release.group, score, minimum
+            );
+            return None;
         }
-        _ => {}
+    }
+
+    if !rule.accepts_season(&release) {
+        info!(
+            "SKIP: Season {} not listed in {}",
+            release.season_or_first(),
+            rule.title
+        );
+        return None;
+    }
+
+    if !rule.types.accepts(&release.episode_type) {
+        info!(
+            "SKIP: Type {} ({}) not accepted by {}",
+            release.episode_type,
+            release.anime_type.as_deref().unwrap_or("no type"),
+            rule.title
+        );
+        return None;
+    }
+
+    // Check episode and version filters.
+    if let Err(reason) = rule.filter(&release) {
+        info!("SKIP: {}", reason);
+        return None;
     }
 
     if get_filesize(&path) == Some(0) {
@@ -227,30 +630,32 @@ fn handle_file(config: &config::Configuration, path: PathBuf) -> Option<()> {
 
     let mut copy_target = config.library.clone();
     copy_target.push(&rule.title);
+    if !create_directory(&copy_target) {
+        return None;
+    }
+    if let Some(folder) = rule.get_season_folder(&release) {
+        copy_target.push(folder);
+        if !create_directory(&copy_target) {
+            return None;
+        }
+    }
+    // Non-episode types may be routed into a subfolder of the rule directory.
+    if let Some(folder) = rule.types.get_folder(&release.episode_type) {
+        copy_target.push(folder);
+        if !create_directory(&copy_target) {
+            return None;
+        }
+    }
+    // Files of release directories keep their path inside the directory, so that files
+    // from different source paths never end up at the same library path.
     let target_directory = copy_target.clone();
-    if !target_directory.exists() {
-        debug!(
-            "Missing directory \"{}\", creating...",
-            &target_directory.to_str().unwrap()
-        );
-        match fs::create_dir(&target_directory) {
-            Ok(()) => {
-                debug!(
-                    "Directory \"{}\" created",
-                    &target_directory.to_str().unwrap()
-                );
-            }
-            Err(error) => {
-                error!(
-                    "Unable to create directory \"{}\" ({}), skipping file...",
-                    &target_directory.display(),
-                    error
-                );
-                return None;
-            }
-        };
+    match directory {
+        Some(directory) if parent.is_some() => {
+            copy_target.push(directory.file_name()?);
+            copy_target.push(path.strip_prefix(directory).ok()?);
+        }
+        _ => copy_target.push(filename),
     }
-    copy_target.push(filename);
 
     let mut copy_file = true;
     if copy_target.exists() {
@@ -267,19 +672,29 @@ fn handle_file(config: &config::Configuration, path: PathBuf) -> Option<()> {
             copy_file = false;
         }
     } else {
-        match find_existing_release(&target_directory, release, rule, config) {
-            Some(ExistingRelease::Superior(path)) => {
+        match find_existing_release(&target_directory, &path, release, rule, config) {
+            Ok(Some(ExistingRelease::Superior(path))) => {
                 info!("Superior release found: \"{}\"", path.display());
                 copy_file = false;
             }
-            Some(ExistingRelease::Inferior(path)) => {
+            Ok(Some(ExistingRelease::Inferior(path))) => {
                 info!("Inferior release found: \"{}\"", path.display());
-                remove_file(&config, &path);
+                remove_release(config, &path);
+            }
+            Ok(None) => info!("No other release"),
+            Err(error) => {
+                error!(
+                    "Unable to read directory \"{}\" ({}), skipping file...",
+                    target_directory.display(),
+                    error
+                );
+                return None;
             }
-            None => info!("No other release"),
         }
     }
-    if copy_file {
+    // The release directory and its subfolders are only created now, since removing an
+    // inferior release may have removed them.
+    if copy_file && copy_target.parent().is_some_and(create_directory) {
         match fs::copy(&path, &copy_target) {
             Ok(_) => {
                 info!("Copied \"{}\" to \"{}\"", filename, &copy_target.display());
@@ -289,9 +704,106 @@ fn handle_file(config: &config::Configuration, path: PathBuf) -> Option<()> {
             }
         };
     }
+    if copy_target.exists() {
+        copy_sidecars(config, &path, &copy_target);
+    }
     Some(())
 }
 
+// Usage: yurizaki [schema | reconcile [--dry-run]] [configuration path]
+fn parse_arguments() -> (Command, Option<String>, bool) {
+    let mut arguments = env::args().skip(1).peekable();
+    let command = match arguments.peek().map(String::as_str) {
+        Some("schema") => Command::Schema,
+        Some("reconcile") => Command::Reconcile,
+        _ => Command::Watch,
+    };
+    if command != Command::Watch {
+        arguments.next();
+    }
+    let mut config_argument = None;
+    let mut dry_run = false;
+    for argument in arguments {
+        if argument == "--dry-run" && command == Command::Reconcile {
+            dry_run = true;
+        } else if config_argument.is_none() {
+            config_argument = Some(argument);
+        } else {
+            warn!("Ignoring argument '{}'", argument);
+        }
+    }
+    (command, config_argument, dry_run)
+}
+
+#[cfg(feature = "json")]
+fn print_schema() {
+    println!("{}", schema::export());
+}
+
+#[cfg(not(feature = "json"))]
+fn print_schema() {
+    error!("yurizaki was built without JSON support; the schema can't be exported.");
+    process::exit(1);
+}
+
+// Reconciles the watched configuration paths with the configuration files and the rules
+// directory of the given configuration. The files are watched directly, so that a single
+// bind-mounted file is still noticed, and their directories are watched as well, since
+// editors that replace files on save would leave a file watch pointing to a deleted file.
+// The root directory is never watched, as it only holds such bind mounts.
+fn watch_configuration(
+    config_watcher: &mut RecommendedWatcher,
+    config: &config::Configuration,
+    config_files: &Mutex<HashSet<PathBuf>>,
+    watched: &mut HashSet<PathBuf>,
+) {
+    let rules_directory = Some(&config.rules_directory).filter(|path| path.is_dir());
+    let directories: HashSet<PathBuf> = config
+        .files
+        .iter()
+        .filter_map(|path| path.parent())
+        .filter(|path| path.parent().is_some())
+        .map(Path::to_path_buf)
+        .chain(rules_directory.cloned())
+        .collect();
+    for path in watched.difference(&directories) {
+        if let Err(error) = config_watcher.unwatch(path) {
+            debug!("Could not unwatch \"{}\": {}", path.display(), error);
+        }
+    }
+    watched.retain(|path| directories.contains(path));
+    // Files are watched again on every reload, because a replaced file is a new file.
+    let mut config_files = config_files.lock().unwrap();
+    for path in config_files.iter() {
+        let _ = config_watcher.unwatch(path);
+    }
+    for path in &config.files {
+        if let Err(error) = config_watcher.watch(path, RecursiveMode::NonRecursive) {
+            warn!(
+                "Could not watch configuration file \"{}\": {}",
+                path.display(),
+                error
+            );
+        }
+    }
+    for path in directories {
+        if watched.contains(&path) {
+            continue;
+        }
+        match config_watcher.watch(&path, RecursiveMode::NonRecursive) {
+            Ok(()) => {
+                watched.insert(path);
+            }
+            Err(error) => warn!(
+                "Could not watch configuration directory \"{}\": {}",
+                path.display(),
+                error
+            ),
+        }
+    }
+    *config_files = config.files.iter().cloned().collect();
+}
+
 fn main() {
     SimpleLogger::new()
         .with_level(LevelFilter::Info)
@@ -299,29 +811,38 @@ fn main() {
         .init()
         .unwrap();
 
-    let Some(config_path) = config::get_path() else {
+    let (command, config_argument, dry_run) = parse_arguments();
+    if command == Command::Schema {
+        print_schema();
+        return;
+    }
+
+    let Some(config_path) = config::get_path(config_argument) else {
         error!("Could not establish configuration directory.");
         process::exit(1);
     };
+    // Watch events use absolute paths, so the configuration path has to be one as well.
+    let config_path = fs::canonicalize(&config_path).unwrap_or(config_path);
     debug!("Loading configuration from \"{}\"", config_path.display());
     let mut configuration = match config::Configuration::new(&config_path) {
         Ok(config) => config,
-        Err(config::Error::Io(error)) => {
+        Err(config::Error::Io(path, error)) => {
             match error.kind() {
                 io::ErrorKind::NotFound => {
                     error!(
                         "Could not find the configuration file in \"{}\".",
-                        config_path.display()
+                        path.display()
                     );
                 }
                 _ => {
                     error!(
-                        "There was a problem with reading the configuration: {}",
+                        "There was a problem with reading the configuration file \"{}\": {}",
+                        path.display(),
                         error
                     );
                 }
             }
-            return;
+            process::exit(1);
         }
         Err(config::Error::MissingSource) => {
             error!("Configuration file is missing a source path");
@@ -331,43 +852,76 @@ fn main() {
             error!("Configuration file is missing a library path");
             process::exit(1);
         }
-        Err(config::Error::YamlError) => {
+        Err(config::Error::Yaml) => {
             error!("There was a problem with reading the configuration Yaml file");
             process::exit(1);
         }
+        #[cfg(feature = "toml")]
+        Err(config::Error::Toml) => {
+            error!("There was a problem with reading the configuration TOML file");
+            process::exit(1);
+        }
+        #[cfg(feature = "json")]
+        Err(config::Error::Json) => {
+            error!("There was a problem with reading the configuration JSON file");
+            process::exit(1);
+        }
     };
 
+    if command == Command::Reconcile {
+        if let Err(reason) = health::check(&configuration) {
+            error!("Unable to reconcile the library: {}", reason);
+            process::exit(1);
+        }
+        reconcile::reconcile(&configuration, dry_run);
+        return;
+    }
+
     let (watch_tx, watch_rx) = channel();
     let mut config_watcher: RecommendedWatcher =
         watcher(watch_tx.clone(), Duration::from_secs(5)).unwrap();
-    config_watcher
-        .watch(&config_path, RecursiveMode::NonRecursive)
-        .unwrap();
+    let config_files = Arc::new(Mutex::new(HashSet::new()));
+    let mut config_directories = HashSet::new();
+    watch_configuration(
+        &mut config_watcher,
+        &configuration,
+        &config_files,
+        &mut config_directories,
+    );
 
-    // TODO: Configurable debounce time (if it's even needed).
-    let debounce_duration = Duration::from_secs(60);
-    let mut watcher: RecommendedWatcher = watcher(watch_tx, debounce_duration).unwrap();
-    match watcher.watch(&configuration.source, RecursiveMode::NonRecursive) {
-        Ok(()) => {}
-        Err(notify::Error::PathNotFound) => {
-            error!(
-                "Could not watch source path \"{}\". \
-                Please verify that the `source` configuration value is set correctly.",
-                configuration.source.display()
-            );
-            process::exit(1);
-        }
-        Err(error) => {
-            error!("Source watch error: {}", error);
-            process::exit(1);
+    // The watcher stops watching when it is dropped, so it has to be kept around.
+    let mut source_watcher = None;
+    let mut monitor = health::Monitor::new();
+    match health::check(&configuration) {
+        Ok(()) => {
+            source_watcher = match watch::SourceWatcher::new(&configuration, watch_tx.clone()) {
+                Ok(watcher) => Some(watcher),
+                Err(notify::Error::PathNotFound) => {
+                    error!(
+                        "Could not watch source path \"{}\". \
+                        Please verify that the `source` configuration value is set correctly.",
+                        configuration.source.display()
+                    );
+                    process::exit(1);
+                }
+                Err(error) => {
+                    error!("Source watch error: {}", error);
+                    process::exit(1);
+                }
+            };
+            if configuration.reconcile_on_startup {
+                reconcile::reconcile(&configuration, false);
+            }
+            // Perform initial scan after.
+            scan_directory(&configuration);
         }
-    };
-
-    // Perform initial scan after.
-    scan_directory(&configuration);
+        Err(reason) => monitor.pause(&reason),
+    }
+    let mut last_scan = Instant::now();
 
     let (action_tx, action_rx) = channel();
-    let cloned_config_path = config_path.clone();
+    let cloned_config_files = Arc::clone(&config_files);
+    let rules_directory = configuration.rules_directory.clone();
     thread::spawn(move || loop {
         let event = match watch_rx.recv() {
             Ok(event) => event,
@@ -376,17 +930,24 @@ fn main() {
                 continue;
             }
         };
-        let path = match event {
-            DebouncedEvent::Create(path) => path,
-            DebouncedEvent::Write(path) => path,
+        // Removed files only matter when they are part of the configuration.
+        let (path, removed) = match event {
+            DebouncedEvent::Create(path) => (path, false),
+            DebouncedEvent::Write(path) => (path, false),
+            DebouncedEvent::Rename(_, path) => (path, false),
+            DebouncedEvent::Remove(path) => (path, true),
             _ => continue,
         };
 
-        let action = if path == cloned_config_path {
+        let is_config = cloned_config_files.lock().unwrap().contains(&path)
+            || (path.parent() == Some(rules_directory.as_path()) && format::is_config_file(&path));
+        let action = if is_config {
             Action {
                 category: ActionCategory::ReloadConfig,
                 path: path.clone(),
             }
+        } else if removed {
+            continue;
         } else {
             Action {
                 category: ActionCategory::Process,
@@ -401,26 +962,220 @@ fn main() {
     });
 
     loop {
-        let action = match action_rx.recv() {
+        // The source is scanned periodically in case any events were missed.
+        let rescan_timeout = configuration
+            .rescan_interval
+            .map(|interval| interval.saturating_sub(last_scan.elapsed()));
+        let timeout = rescan_timeout
+            .into_iter()
+            .fold(monitor.timeout(), Duration::min);
+        let action = match action_rx.recv_timeout(timeout) {
             Ok(action) => action,
-            Err(_) => continue,
+            Err(RecvTimeoutError::Timeout) => {
+                if let Err(reason) = health::check(&configuration) {
+                    monitor.pause(&reason);
+                    continue;
+                }
+                let mut rescan = configuration
+                    .rescan_interval
+                    .is_some_and(|interval| last_scan.elapsed() >= interval);
+                // Watches are lost when the volume disappears, so they are recreated.
+                if monitor.is_paused() || source_watcher.is_none() {
+                    match watch::SourceWatcher::new(&configuration, watch_tx.clone()) {
+                        Ok(watcher) => source_watcher = Some(watcher),
+                        Err(error) => {
+                            warn!("Source watch error: {}", error);
+                            monitor
+                                .pause(&health::Unavailable::Source(configuration.source.clone()));
+                            continue;
+                        }
+                    }
+                    rescan = true;
+                }
+                monitor.resume();
+                if rescan {
+                    debug!("Rescanning source path");
+                    scan_directory(&configuration);
+                    last_scan = Instant::now();
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => continue,
         };
         match action.category {
             ActionCategory::ReloadConfig => {
                 let new_configuration = match config::Configuration::new(&config_path) {
                     Ok(config) => config,
+                    Err(config::Error::Io(path, error)) => {
+                        warn!(
+                            "Unable to read \"{}\" ({}). Old configuration will be used instead.",
+                            path.display(),
+                            error
+                        );
+                        continue;
+                    }
                     Err(_) => {
                         warn!("Unable to reload configuration. Old configuration will be used instead.");
                         continue;
                     }
                 };
+                // A new configuration with unavailable paths is most likely a typo, so it's
+                // only accepted if the paths are the ones already in use. The source is
+                // watched again once they are available.
+                let available = health::check(&new_configuration);
+                if let Err(reason) = &available {
+                    if new_configuration.source != configuration.source
+                        || new_configuration.library != configuration.library
+                    {
+                        warn!("New {}. Old configuration will be used instead.", reason);
+                        continue;
+                    }
+                }
+                // Watches are lost while paused, so the watcher is recreated as well.
+                let restart = source_watcher.is_none()
+                    || monitor.is_paused()
+                    || watch::needs_restart(&configuration, &new_configuration);
+                if available.is_ok() && restart {
+                    match watch::SourceWatcher::new(&new_configuration, watch_tx.clone()) {
+                        Ok(watcher) => source_watcher = Some(watcher),
+                        Err(error) => {
+                            warn!(
+                                "Could not watch source path \"{}\" ({:?}). \
+                                Old configuration will be used instead.",
+                                new_configuration.source.display(),
+                                error
+                            );
+                            continue;
+                        }
+                    }
+                    info!(
+                        "Watching source path \"{}\"",
+                        new_configuration.source.display()
+                    );
+                }
                 configuration = new_configuration;
+                watch_configuration(
+                    &mut config_watcher,
+                    &configuration,
+                    &config_files,
+                    &mut config_directories,
+                );
                 info!("Reloaded configuration:\n{}", configuration);
-                scan_directory(&configuration);
+                match available {
+                    Ok(()) => {
+                        monitor.resume();
+                        scan_directory(&configuration);
+                        last_scan = Instant::now();
+                    }
+                    Err(reason) => monitor.pause(&reason),
+                }
             }
             ActionCategory::Process => {
-                handle_file(&configuration, action.path);
+                if monitor.is_paused() {
+                    // Skipped files are picked up by the scan after resuming.
+                    continue;
+                }
+                if let Err(reason) = health::check(&configuration) {
+                    monitor.pause(&reason);
+                    continue;
+                }
+                // A sidecar arriving after its video triggers processing of the video.
+                let path = if sidecar::is_sidecar(&configuration.sidecars, &action.path) {
+                    match sidecar::find_video(&configuration.media, &action.path) {
+                        Some(video) => video,
+                        None => continue,
+                    }
+                } else {
+                    action.path
+                };
+                handle_path(&configuration, path);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Loads a configuration with a single "Title" rule from a temporary directory, which
+    // also holds the files of the compared releases.
+    fn load_configuration(name: &str, rule: &str) -> (PathBuf, config::Configuration) {
+        let directory = env::temp_dir().join(format!("yurizaki-{}-{}", name, process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("config.yml");
+        let source = format!("source: .\nlibrary: .\nTitle:\n{}", rule);
+        fs::write(&path, source).unwrap();
+        let configuration = config::Configuration::new(&path).unwrap();
+        (directory, configuration)
+    }
+
+    fn compare(
+        config: &config::Configuration,
+        directory: &Path,
+        filename: &str,
+        other_filename: &str,
+    ) -> Option<Ordering> {
+        let release = anime::Release::from(filename, &config.parser).unwrap();
+        let other = anime::Release::from(other_filename, &config.parser).unwrap();
+        let rule = config.get_rule(&release).unwrap();
+        compare_releases(
+            rule,
+            &release,
+            &directory.join(filename),
+            &other,
+            &directory.join(other_filename),
+        )
+    }
+
+    #[test]
+    fn ranks_releases_by_quality() {
+        let (directory, config) = load_configuration(
+            "quality",
+            "  groups: [A, B]\n  quality:\n    resolution: [1080p, 720p]\n    \
+            source: [BD]\n    before_group: [source]\n",
+        );
+        let compare =
+            |filename, other_filename| compare(&config, &directory, filename, other_filename);
+        assert_eq!(
+            compare("[A] Title - 01 (1080p).mkv", "[A] Title - 01 (720p).mkv"),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            compare("[A] Title - 01 (720p).mkv", "[B] Title - 01 (1080p).mkv"),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            compare("[B] Title - 01 (BD 720p).mkv", "[A] Title - 01 (1080p).mkv"),
+            Some(Ordering::Greater)
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn ranks_releases_by_group_and_tie_breakers() {
+        let (directory, config) = load_configuration("groups", "  groups: [[A, B], C]\n");
+        fs::write(directory.join("[A] Title - 02.mkv"), "larger").unwrap();
+        fs::write(directory.join("[B] Title - 02.mkv"), "small").unwrap();
+        let compare =
+            |filename, other_filename| compare(&config, &directory, filename, other_filename);
+        assert_eq!(
+            compare("[A] Title - 01.mkv", "[C] Title - 01v2.mkv"),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            compare("[A] Title - 01.mkv", "[B] Title - 01v2.mkv"),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            compare("[A] Title - 02.mkv", "[B] Title - 02.mkv"),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            compare("[A] Title - 01.mkv", "[B] Title - 01.mkv"),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(compare("[A] Title - 01.mkv", "[D] Title - 01.mkv"), None);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
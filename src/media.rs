@@ -0,0 +1,88 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::config::Media;
+
+const MATROSKA_MAGIC: [u8; 4] = [0x1A, 0x45, 0xDF, 0xA3];
+const MP4_MAGIC: &[u8; 4] = b"ftyp";
+
+const MATROSKA_EXTENSIONS: [&str; 3] = ["mkv", "mka", "webm"];
+const MP4_EXTENSIONS: [&str; 3] = ["mp4", "m4v", "mov"];
+
+#[derive(Debug)]
+pub enum NotMedia {
+    Extension(Option<String>),
+    Sample,
+    Content,
+}
+
+impl fmt::Display for NotMedia {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NotMedia::Extension(Some(extension)) => {
+                write!(f, "extension \".{}\" is not allowed", extension)
+            }
+            NotMedia::Extension(None) => write!(f, "file has no extension"),
+            NotMedia::Sample => write!(f, "file is a sample"),
+            NotMedia::Content => write!(f, "file contents do not match the extension"),
+        }
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    Some(path.extension()?.to_str()?.to_lowercase())
+}
+
+pub fn has_media_extension(media: &Media, path: &Path) -> bool {
+    let Some(extension) = extension(path) else {
+        return false;
+    };
+    media
+        .extensions
+        .iter()
+        .any(|e| e.eq_ignore_ascii_case(&extension))
+}
+
+fn is_sample(path: &Path) -> bool {
+    let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+        return false;
+    };
+    stem.split(|c: char| !c.is_alphanumeric())
+        .any(|word| word.eq_ignore_ascii_case("sample"))
+}
+
+fn read_header(path: &Path) -> Option<[u8; 12]> {
+    let mut file = File::open(path).ok()?;
+    let mut header = [0u8; 12];
+    file.read_exact(&mut header).ok()?;
+    Some(header)
+}
+
+// Only Matroska and MP4 are sniffed; other allowed containers are trusted by extension.
+fn matches_content(path: &Path, extension: &str) -> bool {
+    if MATROSKA_EXTENSIONS.contains(&extension) {
+        read_header(path).is_some_and(|header| header[..4] == MATROSKA_MAGIC)
+    } else if MP4_EXTENSIONS.contains(&extension) {
+        read_header(path).is_some_and(|header| &header[4..8] == MP4_MAGIC)
+    } else {
+        true
+    }
+}
+
+pub fn check(media: &Media, path: &Path) -> Result<(), NotMedia> {
+    if !has_media_extension(media, path) {
+        return Err(NotMedia::Extension(extension(path)));
+    }
+    if media.skip_samples && is_sample(path) {
+        return Err(NotMedia::Sample);
+    }
+    if media.sniff {
+        let extension = extension(path).unwrap_or_default();
+        if !matches_content(path, &extension) {
+            return Err(NotMedia::Content);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use std::cmp::Ordering;
+use std::path::PathBuf;
+use std::ptr;
+
+use log::{debug, info};
+
+use crate::anime::Release;
+use crate::config::{Configuration, Rule};
+use crate::{compare_releases, find_library_videos, is_same_episode, make_release};
+
+struct LibraryFile {
+    path: PathBuf,
+    release: Release,
+}
+
+// Files are parsed with the rule of the directory and have to be within its scope.
+fn read_file(
+    config: &Configuration,
+    rule: &Rule,
+    path: PathBuf,
+    parent: Option<&Release>,
+) -> Option<LibraryFile> {
+    let filename = path.file_name()?.to_str()?;
+    let release = make_release(config, filename, parent, Some(rule))?;
+    if !rule.scope.contains(&release) {
+        return None;
+    }
+    if rule.get_score(&release.group).is_none() {
+        debug!(
+            "Group \"{}\" of \"{}\" is not listed",
+            release.group, filename
+        );
+        return None;
+    }
+    Some(LibraryFile { path, release })
+}
+
+fn reconcile_directory(config: &Configuration, rule: &Rule, dry_run: bool) {
+    // The season and type subfolders of the rule directory are included.
+    let directory = config.library.join(&rule.title);
+    let Ok(mut videos) = find_library_videos(config, &directory, true) else {
+        return;
+    };
+    videos.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    // Releases of the same episode are grouped together.
+    let mut episodes: Vec<Vec<LibraryFile>> = Vec::new();
+    for (path, parent) in videos {
+        let Some(file) = read_file(config, rule, path, parent.as_ref()) else {
+            continue;
+        };
+        let episode = episodes
+            .iter_mut()
+            .find(|files| is_same_episode(&files[0].release, &file.release));
+        match episode {
+            Some(files) => files.push(file),
+            None => episodes.push(vec![file]),
+        }
+    }
+
+    for files in episodes.iter().filter(|files| files.len() > 1) {
+        let compare = |a: &LibraryFile, b: &LibraryFile| {
+            compare_releases(rule, &a.release, &a.path, &b.release, &b.path)
+        };
+        let mut best = &files[0];
+        for file in &files[1..] {
+            if compare(file, best) == Some(Ordering::Greater) {
+                best = file;
+            }
+        }
+        // Only releases that are strictly inferior are removed, since equally ranked
+        // releases can't be told apart.
+        for file in files.iter().filter(|file| !ptr::eq(*file, best)) {
+            if compare(best, file) != Some(Ordering::Greater) {
+                info!(
+                    "KEEP: \"{}\" is ranked equally to \"{}\"",
+                    file.path.display(),
+                    best.path.display()
+                );
+                continue;
+            }
+            if dry_run {
+                info!(
+                    "DRY RUN: \"{}\" is inferior to \"{}\"",
+                    file.path.display(),
+                    best.path.display()
+                );
+            } else {
+                info!(
+                    "REMOVE: \"{}\" is inferior to \"{}\"",
+                    file.path.display(),
+                    best.path.display()
+                );
+                crate::remove_release(config, &file.path);
+            }
+        }
+    }
+}
+
+// Removes releases from the library that are inferior to another release of the same
+// episode, according to the rules. With a dry run, the releases are only listed.
+pub fn reconcile(config: &Configuration, dry_run: bool) {
+    for rule in &config.rules {
+        reconcile_directory(config, rule, dry_run);
+    }
+}
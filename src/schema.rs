@@ -0,0 +1,507 @@
+use std::fmt::Display;
+
+use yaml_rust::{yaml, Yaml};
+
+use crate::anime::{EpisodeType, QualityCategory};
+use crate::config::{TieBreaker, WatchMode};
+
+// Names of the configuration keys, shared by the configuration readers and the definitions.
+pub mod keys {
+    pub const ACCEPT: &str = "accept";
+    pub const ALIASES: &str = "aliases";
+    pub const ALLOWED_DELIMITERS: &str = "allowed_delimiters";
+    pub const APPEND: &str = "append";
+    pub const AUDIO: &str = "audio";
+    pub const BEFORE_GROUP: &str = "before_group";
+    pub const DEFAULTS: &str = "defaults";
+    pub const DIRECTORIES: &str = "directories";
+    pub const EPISODE: &str = "episode";
+    pub const EPISODES: &str = "episodes";
+    pub const EXCLUDE: &str = "exclude";
+    pub const EXTENDS: &str = "extends";
+    pub const EXTENSIONS: &str = "extensions";
+    pub const FILTER: &str = "filter";
+    pub const FOLDERS: &str = "folders";
+    pub const FUZZY_THRESHOLD: &str = "fuzzy_threshold";
+    pub const GROUP_ALIASES: &str = "group_aliases";
+    pub const GROUP_SETS: &str = "group_sets";
+    pub const GROUPS: &str = "groups";
+    pub const IGNORED_STRINGS: &str = "ignored_strings";
+    pub const INCLUDE: &str = "include";
+    pub const LIBRARY: &str = "library";
+    pub const MAXIMUM: &str = "maximum";
+    pub const MEDIA: &str = "media";
+    pub const MINIMUM: &str = "minimum";
+    pub const PARSE_EPISODE_NUMBER: &str = "parse_episode_number";
+    pub const PARSE_EPISODE_TITLE: &str = "parse_episode_title";
+    pub const PARSE_FILE_EXTENSION: &str = "parse_file_extension";
+    pub const PARSE_RELEASE_GROUP: &str = "parse_release_group";
+    pub const PARSER: &str = "parser";
+    pub const POLL_INTERVAL: &str = "poll_interval";
+    pub const PREPEND: &str = "prepend";
+    pub const QUALITY: &str = "quality";
+    pub const RECONCILE_ON_STARTUP: &str = "reconcile_on_startup";
+    #[cfg(feature = "regex")]
+    pub const REGEX: &str = "regex";
+    pub const RESCAN_INTERVAL: &str = "rescan_interval";
+    pub const RESOLUTION: &str = "resolution";
+    pub const SCORE: &str = "score";
+    pub const SEASON: &str = "season";
+    pub const SEASONS: &str = "seasons";
+    pub const SET: &str = "set";
+    pub const SIDECARS: &str = "sidecars";
+    pub const SKIP_SAMPLES: &str = "skip_samples";
+    pub const SNIFF: &str = "sniff";
+    pub const SOURCE: &str = "source";
+    pub const STRIP: &str = "strip";
+    pub const TIE_BREAKERS: &str = "tie_breakers";
+    #[cfg(feature = "regex")]
+    pub const TITLE_PATTERN: &str = "title_pattern";
+    pub const TRASH: &str = "trash";
+    pub const TYPE_MAPPING: &str = "type_mapping";
+    pub const TYPES: &str = "types";
+    pub const VERSION: &str = "version";
+    pub const VIDEO: &str = "video";
+    pub const WATCH_MODE: &str = "watch_mode";
+    pub const YEAR: &str = "year";
+}
+
+// Definitions of the configuration keys. They are used to warn about unknown keys when the
+// configuration is read and to export a JSON Schema for editors.
+#[cfg_attr(not(feature = "json"), allow(dead_code))]
+pub enum Value {
+    Boolean,
+    Integer,
+    Number,
+    String,
+    Null,
+    Enum(fn() -> Vec<String>),
+    List(&'static Value),
+    // A dictionary with arbitrary keys and values of the same kind.
+    Map(&'static Value),
+    Object(&'static [Key]),
+    AnyOf(&'static [Value]),
+    Rule,
+}
+
+#[cfg_attr(not(feature = "json"), allow(dead_code))]
+pub struct Key {
+    pub name: &'static str,
+    pub value: Value,
+    pub description: &'static str,
+}
+
+const fn key(name: &'static str, value: Value, description: &'static str) -> Key {
+    Key {
+        name,
+        value,
+        description,
+    }
+}
+
+fn names<T: Display>(values: &[T]) -> Vec<String> {
+    values.iter().map(ToString::to_string).collect()
+}
+
+fn episode_types() -> Vec<String> {
+    names(&EpisodeType::ALL)
+}
+
+fn quality_categories() -> Vec<String> {
+    names(&QualityCategory::ALL)
+}
+
+fn watch_modes() -> Vec<String> {
+    names(&WatchMode::ALL)
+}
+
+fn tie_breakers() -> Vec<String> {
+    names(&TieBreaker::ALL)
+}
+
+const STRINGS: Value = Value::List(&Value::String);
+const STRING_OR_STRINGS: Value = Value::AnyOf(&[Value::String, STRINGS]);
+const GROUP_LIST: Value = Value::List(&Value::AnyOf(&[Value::String, STRINGS]));
+
+const GROUP_SET_REFERENCE: &[Key] = &[key(
+    keys::SET,
+    Value::String,
+    "Name of the group set to insert into the list.",
+)];
+
+// Group lists of rules can insert group sets at any position.
+const RULE_GROUP_LIST: Value = Value::List(&Value::AnyOf(&[
+    Value::String,
+    STRINGS,
+    Value::Object(GROUP_SET_REFERENCE),
+]));
+
+const GROUP_SET_EXTENSION: &[Key] = &[
+    key(
+        keys::EXTENDS,
+        Value::String,
+        "Name of the group set to extend.",
+    ),
+    key(
+        keys::PREPEND,
+        RULE_GROUP_LIST,
+        "Groups ranked above the group set.",
+    ),
+    key(
+        keys::APPEND,
+        RULE_GROUP_LIST,
+        "Groups ranked below the group set.",
+    ),
+];
+
+const PARSER: &[Key] = &[
+    key(
+        keys::ALLOWED_DELIMITERS,
+        Value::String,
+        "Characters anitomy treats as delimiters.",
+    ),
+    key(
+        keys::IGNORED_STRINGS,
+        STRINGS,
+        "Strings anitomy should ignore.",
+    ),
+    key(keys::PARSE_EPISODE_NUMBER, Value::Boolean, ""),
+    key(keys::PARSE_EPISODE_TITLE, Value::Boolean, ""),
+    key(keys::PARSE_FILE_EXTENSION, Value::Boolean, ""),
+    key(keys::PARSE_RELEASE_GROUP, Value::Boolean, ""),
+    key(
+        keys::STRIP,
+        STRINGS,
+        "Strings removed from filenames before parsing.",
+    ),
+];
+
+const SIDECARS: &[Key] = &[
+    key(keys::EXTENSIONS, STRINGS, "Extensions of sidecar files."),
+    key(keys::DIRECTORIES, STRINGS, "Names of sidecar directories."),
+];
+
+const MEDIA: &[Key] = &[
+    key(keys::EXTENSIONS, STRINGS, "Extensions of media files."),
+    key(
+        keys::SNIFF,
+        Value::Boolean,
+        "Verify the magic bytes of Matroska and MP4 files.",
+    ),
+    key(keys::SKIP_SAMPLES, Value::Boolean, "Skip sample files."),
+];
+
+const MINIMUM: &[Key] = &[
+    key(keys::EPISODE, Value::Integer, "Minimum episode number."),
+    key(keys::SCORE, Value::Integer, "Minimum group score."),
+    key(keys::VERSION, Value::Integer, "Minimum release version."),
+];
+
+const MAXIMUM: &[Key] = &[
+    key(keys::EPISODE, Value::Integer, "Maximum episode number."),
+    key(keys::VERSION, Value::Integer, "Maximum release version."),
+];
+
+const FILTER: &[Key] = &[
+    key(
+        keys::INCLUDE,
+        Value::List(&Value::Integer),
+        "Episodes copied regardless of the minimum and maximum episode.",
+    ),
+    key(
+        keys::EXCLUDE,
+        Value::List(&Value::Integer),
+        "Episodes that are never copied.",
+    ),
+    key(
+        keys::GROUPS,
+        Value::Map(&Value::String),
+        "Episode ranges (such as 1-6) of groups.",
+    ),
+];
+
+const QUALITY: &[Key] = &[
+    key(
+        keys::RESOLUTION,
+        STRINGS,
+        "Preferred resolutions, best first.",
+    ),
+    key(keys::SOURCE, STRINGS, "Preferred sources, best first."),
+    key(keys::VIDEO, STRINGS, "Preferred video codecs, best first."),
+    key(keys::AUDIO, STRINGS, "Preferred audio codecs, best first."),
+    key(
+        keys::BEFORE_GROUP,
+        Value::List(&Value::Enum(quality_categories)),
+        "Categories compared before the release group.",
+    ),
+];
+
+const TYPES: &[Key] = &[
+    key(
+        keys::ACCEPT,
+        Value::List(&Value::Enum(episode_types)),
+        "Accepted release types.",
+    ),
+    key(
+        keys::FOLDERS,
+        Value::Map(&Value::String),
+        "Folders of release types inside the rule directory.",
+    ),
+];
+
+pub const RULE: &[Key] = &[
+    key(
+        keys::GROUPS,
+        Value::AnyOf(&[
+            Value::String,
+            RULE_GROUP_LIST,
+            Value::Map(&Value::Integer),
+            Value::Object(GROUP_SET_EXTENSION),
+        ]),
+        "Release groups from best to worst, a group set or groups with scores.",
+    ),
+    key(
+        keys::GROUP_ALIASES,
+        Value::Map(&STRINGS),
+        "Other names of release groups.",
+    ),
+    key(keys::ALIASES, STRINGS, "Other titles of the anime."),
+    #[cfg(feature = "regex")]
+    key(
+        keys::REGEX,
+        STRINGS,
+        "Regular expressions with episode and group captures.",
+    ),
+    #[cfg(feature = "regex")]
+    key(
+        keys::TITLE_PATTERN,
+        STRING_OR_STRINGS,
+        "Regular expressions matched against parsed titles.",
+    ),
+    key(keys::MINIMUM, Value::Object(MINIMUM), ""),
+    key(keys::MAXIMUM, Value::Object(MAXIMUM), ""),
+    key(keys::FILTER, Value::Object(FILTER), ""),
+    key(keys::QUALITY, Value::Object(QUALITY), ""),
+    key(keys::TYPES, Value::Object(TYPES), ""),
+    key(
+        keys::SEASONS,
+        Value::Map(&Value::AnyOf(&[Value::String, Value::Integer, Value::Null])),
+        "Accepted seasons and their folders.",
+    ),
+    key(
+        keys::PARSER,
+        Value::Object(PARSER),
+        "Parser options of the rule.",
+    ),
+    key(
+        keys::TIE_BREAKERS,
+        Value::List(&Value::Enum(tie_breakers)),
+        "Resolves ties between releases with equal group ranks.",
+    ),
+    key(
+        keys::EPISODES,
+        Value::AnyOf(&[Value::Integer, Value::String]),
+        "Episode range (such as 1-12 or 13-) of the rule.",
+    ),
+    key(keys::SEASON, Value::Integer, "Season of the rule."),
+    key(keys::YEAR, Value::Integer, "Year of the rule."),
+];
+
+pub const CONFIGURATION: &[Key] = &[
+    key(
+        keys::LIBRARY,
+        Value::String,
+        "Directory releases are copied to.",
+    ),
+    key(
+        keys::SOURCE,
+        Value::String,
+        "Directory releases are copied from.",
+    ),
+    key(
+        keys::TRASH,
+        Value::Boolean,
+        "Move replaced releases to the trash.",
+    ),
+    key(
+        keys::INCLUDE,
+        STRING_OR_STRINGS,
+        "Other configuration files.",
+    ),
+    key(
+        keys::GROUP_SETS,
+        Value::Map(&GROUP_LIST),
+        "Named lists of release groups.",
+    ),
+    key(
+        keys::DEFAULTS,
+        Value::Rule,
+        "Values inherited by every rule.",
+    ),
+    key(keys::SIDECARS, Value::Object(SIDECARS), ""),
+    key(keys::MEDIA, Value::Object(MEDIA), ""),
+    key(
+        keys::TYPE_MAPPING,
+        Value::Map(&Value::Enum(episode_types)),
+        "Types of anitomy type strings.",
+    ),
+    key(keys::PARSER, Value::Object(PARSER), "Parser options."),
+    key(
+        keys::FUZZY_THRESHOLD,
+        Value::Number,
+        "Minimum similarity of fuzzy title matches.",
+    ),
+    key(
+        keys::WATCH_MODE,
+        Value::Enum(watch_modes),
+        "How changes in the source directory are detected.",
+    ),
+    key(
+        keys::POLL_INTERVAL,
+        Value::Integer,
+        "Seconds between polls of the source directory.",
+    ),
+    key(
+        keys::RESCAN_INTERVAL,
+        Value::Integer,
+        "Seconds between full scans of the source directory.",
+    ),
+    key(
+        keys::RECONCILE_ON_STARTUP,
+        Value::Boolean,
+        "Remove inferior releases from the library on startup.",
+    ),
+    #[cfg(feature = "regex")]
+    key(
+        keys::REGEX,
+        STRINGS,
+        "Regular expressions with title, episode and group captures.",
+    ),
+];
+
+fn find(keys: &'static [Key], name: &str) -> Option<&'static Key> {
+    keys.iter().find(|key| key.name == name)
+}
+
+// Any other top-level key is a rule.
+pub fn check_configuration(hash: &yaml::Hash) {
+    for (key, value) in hash {
+        let Some(name) = key.as_str() else {
+            continue;
+        };
+        match find(CONFIGURATION, name) {
+            Some(key) => check(value, &key.value, name),
+            None if value.as_hash().is_none() => {
+                log::warn!("Unknown configuration key '{}'", name)
+            }
+            None => check(value, &Value::Rule, name),
+        }
+    }
+}
+
+fn check_keys(hash: &yaml::Hash, keys: &'static [Key], path: &str) {
+    for (key, value) in hash {
+        let Some(name) = key.as_str() else {
+            continue;
+        };
+        let path = format!("{}.{}", path, name);
+        match find(keys, name) {
+            Some(key) => check(value, &key.value, &path),
+            None => log::warn!("Unknown configuration key '{}'", path),
+        }
+    }
+}
+
+fn matches_kind(value: &Yaml, definition: &Value) -> bool {
+    match (definition, value) {
+        (Value::Boolean, Yaml::Boolean(_)) => true,
+        (Value::Integer, Yaml::Integer(_)) => true,
+        (Value::Number, Yaml::Integer(_) | Yaml::Real(_)) => true,
+        (Value::String | Value::Enum(_), Yaml::String(_)) => true,
+        (Value::Null, Yaml::Null) => true,
+        (Value::List(_), Yaml::Array(_)) => true,
+        (Value::Map(_) | Value::Object(_) | Value::Rule, Yaml::Hash(_)) => true,
+        (Value::AnyOf(definitions), _) => definitions.iter().any(|d| matches_kind(value, d)),
+        _ => false,
+    }
+}
+
+// Only unknown keys are reported here; the readers handle values of the wrong kind. With
+// several alternatives, the first one of the same kind as the value is checked.
+fn check(value: &Yaml, definition: &Value, path: &str) {
+    match (definition, value) {
+        (Value::Rule, Yaml::Hash(hash)) => check_keys(hash, RULE, path),
+        (Value::Object(keys), Yaml::Hash(hash)) => check_keys(hash, keys, path),
+        (Value::Map(definition), Yaml::Hash(hash)) => {
+            for (key, value) in hash {
+                let name = key.as_str().unwrap_or_default();
+                check(value, definition, &format!("{}.{}", path, name));
+            }
+        }
+        (Value::List(definition), Yaml::Array(array)) => {
+            for item in array {
+                check(item, definition, path);
+            }
+        }
+        (Value::AnyOf(definitions), _) => {
+            if let Some(definition) = definitions.iter().find(|d| matches_kind(value, d)) {
+                check(value, definition, path);
+            }
+        }
+        _ => (),
+    }
+}
+
+#[cfg(feature = "json")]
+fn to_json(value: &Value) -> serde_json::Value {
+    use serde_json::json;
+    match value {
+        Value::Boolean => json!({ "type": "boolean" }),
+        Value::Integer => json!({ "type": "integer" }),
+        Value::Number => json!({ "type": "number" }),
+        Value::String => json!({ "type": "string" }),
+        Value::Null => json!({ "type": "null" }),
+        Value::Enum(values) => json!({ "enum": values() }),
+        Value::List(items) => json!({ "type": "array", "items": to_json(items) }),
+        Value::Map(values) => json!({ "type": "object", "additionalProperties": to_json(values) }),
+        Value::Object(keys) => object_to_json(keys),
+        Value::AnyOf(values) => json!({ "anyOf": values.iter().map(to_json).collect::<Vec<_>>() }),
+        Value::Rule => json!({ "$ref": "#/definitions/rule" }),
+    }
+}
+
+#[cfg(feature = "json")]
+fn properties_to_json(keys: &[Key]) -> serde_json::Map<String, serde_json::Value> {
+    let mut properties = serde_json::Map::new();
+    for key in keys {
+        let mut property = to_json(&key.value);
+        if !key.description.is_empty() {
+            property["description"] = key.description.into();
+        }
+        properties.insert(key.name.to_string(), property);
+    }
+    properties
+}
+
+#[cfg(feature = "json")]
+fn object_to_json(keys: &[Key]) -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": properties_to_json(keys),
+        "additionalProperties": false,
+    })
+}
+
+// JSON Schema (draft 7) of the configuration, where every unknown top-level key is a rule.
+#[cfg(feature = "json")]
+pub fn export() -> String {
+    let schema = serde_json::json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "yurizaki configuration",
+        "type": "object",
+        "properties": properties_to_json(CONFIGURATION),
+        "additionalProperties": { "$ref": "#/definitions/rule" },
+        "definitions": { "rule": object_to_json(RULE) },
+    });
+    serde_json::to_string_pretty(&schema).unwrap_or_default()
+}
@@ -0,0 +1,139 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Media, Sidecars};
+use crate::media;
+
+fn has_sidecar_extension(sidecars: &Sidecars, path: &Path) -> bool {
+    let Some(extension) = path.extension().and_then(|e| e.to_str()) else {
+        return false;
+    };
+    sidecars
+        .extensions
+        .iter()
+        .any(|e| e.eq_ignore_ascii_case(extension))
+}
+
+fn stem(path: &Path) -> Option<&str> {
+    path.file_stem()?.to_str()
+}
+
+// Returns the part of the sidecar filename that follows the video's base filename,
+// e.g. ".en.ass" for "Title - 05.mkv" and "Title - 05.en.ass". A number following the
+// base filename belongs to another episode, such as "Title - 12.5.ass" for "Title - 12.mkv".
+fn suffix<'a>(video: &Path, sidecar: &'a Path) -> Option<&'a str> {
+    let stem = stem(video)?;
+    let filename = sidecar.file_name()?.to_str()?;
+    let suffix = filename.strip_prefix(stem)?;
+    let first_part = suffix.strip_prefix('.')?.split('.').next()?;
+    if !first_part.is_empty() && first_part.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(suffix)
+}
+
+pub fn is_sidecar(sidecars: &Sidecars, path: &Path) -> bool {
+    path.is_file() && has_sidecar_extension(sidecars, path)
+}
+
+// Finds all sidecar files that share the base filename of the given video, unless they
+// belong to another video with a longer matching base filename.
+pub fn find_sidecars(sidecars: &Sidecars, media: &Media, video: &Path) -> Vec<PathBuf> {
+    let Some(directory) = video.parent() else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path != video && is_sidecar(sidecars, path))
+        .filter(|path| suffix(video, path).is_some())
+        .filter(|path| {
+            find_video(media, path).is_some_and(|other| other.file_name() == video.file_name())
+        })
+        .collect()
+}
+
+// Finds the video that a sidecar file belongs to. When several videos match, the one
+// with the longest base filename is chosen.
+pub fn find_video(media: &Media, sidecar: &Path) -> Option<PathBuf> {
+    let entries = fs::read_dir(sidecar.parent()?).ok()?;
+    entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && media::has_media_extension(media, path))
+        .filter(|path| suffix(path, sidecar).is_some())
+        .max_by_key(|path| stem(path).map_or(0, str::len))
+}
+
+// Builds the library path for a sidecar based on the library path of its video.
+pub fn target_path(video: &Path, video_target: &Path, sidecar: &Path) -> Option<PathBuf> {
+    let suffix = suffix(video, sidecar)?;
+    let target_stem = stem(video_target)?;
+    Some(video_target.with_file_name(format!("{}{}", target_stem, suffix)))
+}
+
+// Finds the sidecar directories (such as fonts) that are located next to the video.
+pub fn find_directories(sidecars: &Sidecars, video: &Path) -> Vec<PathBuf> {
+    let Some(directory) = video.parent() else {
+        return Vec::new();
+    };
+    sidecars
+        .directories
+        .iter()
+        .map(|name| directory.join(name))
+        .filter(|path| path.is_dir())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs::File;
+    use std::process;
+
+    #[test]
+    fn finds_suffixes() {
+        let video = Path::new("Title - 12.mkv");
+        let suffix = |sidecar: &'static str| suffix(video, Path::new(sidecar));
+        assert_eq!(suffix("Title - 12.ass"), Some(".ass"));
+        assert_eq!(suffix("Title - 12.en.ass"), Some(".en.ass"));
+        assert_eq!(suffix("Title - 12.5.ass"), None);
+        assert_eq!(suffix("Title - 1.ass"), None);
+        assert_eq!(suffix("Title - 120.ass"), None);
+    }
+
+    #[test]
+    fn finds_sidecars_of_their_own_video() {
+        let directory = env::temp_dir().join(format!("yurizaki-sidecars-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let files = [
+            "Title - 12.mkv",
+            "Title - 12.5.mkv",
+            "Title - 12.en.mkv",
+            "Title - 12.ass",
+            "Title - 12.de.srt",
+            "Title - 12.5.ass",
+            "Title - 12.en.ass",
+        ];
+        for file in files {
+            File::create(directory.join(file)).unwrap();
+        }
+        let mut sidecars = find_sidecars(
+            &Sidecars::default(),
+            &Media::default(),
+            &directory.join("Title - 12.mkv"),
+        );
+        sidecars.sort();
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(
+            sidecars,
+            vec![
+                directory.join("Title - 12.ass"),
+                directory.join("Title - 12.de.srt"),
+            ]
+        );
+    }
+}
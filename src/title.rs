@@ -0,0 +1,72 @@
+use unicode_normalization::UnicodeNormalization;
+
+// Reduces a title to a form where trivial differences (full-width characters, case,
+// punctuation, whitespace and a trailing year) no longer matter.
+pub fn normalize(title: &str) -> String {
+    let folded: String = title
+        .nfkc()
+        .flat_map(char::to_lowercase)
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    let mut words: Vec<&str> = folded.split_whitespace().collect();
+    if words.len() > 1 && words.last().is_some_and(|word| is_year(word)) {
+        words.pop();
+    }
+    words.join(" ")
+}
+
+fn is_year(word: &str) -> bool {
+    word.len() == 4
+        && word.chars().all(|c| c.is_ascii_digit())
+        && (word.starts_with("19") || word.starts_with("20"))
+}
+
+// Levenshtein distance based similarity between 0.0 (nothing in common) and 1.0 (equal).
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_char) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    1.0 - previous[b.len()] as f64 / longest as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_titles() {
+        assert_eq!(normalize("Yuru Camp△"), "yuru camp");
+        assert_eq!(normalize("ＹＵＲＵ　ＣＡＭＰ"), "yuru camp");
+        assert_eq!(
+            normalize("Re:Zero - Starting Life"),
+            "re zero starting life"
+        );
+        assert_eq!(normalize("Hunter x Hunter (2011)"), "hunter x hunter");
+        assert_eq!(normalize("Hunter x Hunter 2011"), "hunter x hunter");
+        assert_eq!(normalize("2011"), "2011");
+        assert_eq!(normalize("Title 2100"), "title 2100");
+    }
+
+    #[test]
+    fn measures_similarity() {
+        assert_eq!(similarity("", ""), 1.0);
+        assert_eq!(similarity("abc", "abc"), 1.0);
+        assert_eq!(similarity("abc", "xyz"), 0.0);
+        assert_eq!(similarity("abcd", "abce"), 0.75);
+        assert_eq!(similarity("abc", "abcd"), 0.75);
+        assert_eq!(similarity("abcd", ""), 0.0);
+    }
+}
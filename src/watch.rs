@@ -0,0 +1,147 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use notify::{DebouncedEvent, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::config::{Configuration, WatchMode};
+
+// TODO: Configurable debounce time (if it's even needed).
+const DEBOUNCE_DURATION: Duration = Duration::from_secs(60);
+
+// File systems on which changes made by other hosts don't generate native events.
+const NETWORK_FILESYSTEMS: [&str; 12] = [
+    "9p",
+    "afs",
+    "ceph",
+    "cifs",
+    "davfs",
+    "fuse.rclone",
+    "fuse.sshfs",
+    "glusterfs",
+    "ncpfs",
+    "nfs",
+    "nfs4",
+    "smb3",
+];
+
+// notify's watchers can't be used as trait objects, so the chosen watcher is wrapped.
+pub enum SourceWatcher {
+    Native(RecommendedWatcher),
+    Poll(PollWatcher),
+}
+
+impl SourceWatcher {
+    // Starts watching the source directory of the configuration.
+    pub fn new(
+        config: &Configuration,
+        tx: Sender<DebouncedEvent>,
+    ) -> Result<SourceWatcher, notify::Error> {
+        let poll = match config.watch_mode {
+            WatchMode::Auto => is_network_filesystem(&config.source),
+            WatchMode::Native => false,
+            WatchMode::Poll => true,
+        };
+        let mut watcher = if poll {
+            log::info!(
+                "Polling source path \"{}\" every {} seconds",
+                config.source.display(),
+                config.poll_interval.as_secs()
+            );
+            SourceWatcher::Poll(PollWatcher::new(tx, config.poll_interval)?)
+        } else {
+            SourceWatcher::Native(RecommendedWatcher::new(tx, DEBOUNCE_DURATION)?)
+        };
+        watcher.watch(&config.source)?;
+        Ok(watcher)
+    }
+
+    fn watch(&mut self, path: &Path) -> Result<(), notify::Error> {
+        match self {
+            SourceWatcher::Native(watcher) => watcher.watch(path, RecursiveMode::Recursive),
+            SourceWatcher::Poll(watcher) => watcher.watch(path, RecursiveMode::Recursive),
+        }
+    }
+}
+
+// Whether the watcher of the old configuration can't be used for the new one.
+pub fn needs_restart(old: &Configuration, new: &Configuration) -> bool {
+    old.source != new.source
+        || old.watch_mode != new.watch_mode
+        || old.poll_interval != new.poll_interval
+}
+
+// Mount points in /proc/self/mounts escape spaces and other special characters as octal.
+fn unescape_mount_point(mount_point: &str) -> PathBuf {
+    let mut bytes = Vec::new();
+    let mut rest = mount_point.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let octal = tail.get(..3).and_then(|digits| {
+            let digits = std::str::from_utf8(digits).ok()?;
+            u8::from_str_radix(digits, 8).ok()
+        });
+        match (byte, octal) {
+            (b'\\', Some(value)) => {
+                bytes.push(value);
+                rest = &tail[3..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// Finds the file system type of the mount containing the path. Only Linux is supported;
+// other platforms are never detected as network file systems.
+fn is_network_filesystem(path: &Path) -> bool {
+    let Ok(path) = fs::canonicalize(path) else {
+        return false;
+    };
+    let Ok(mounts) = fs::read_to_string("/proc/self/mounts") else {
+        return false;
+    };
+    let filesystem = mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let mount_point = unescape_mount_point(fields.nth(1)?);
+            let filesystem = fields.next()?;
+            Some((mount_point, filesystem))
+        })
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.components().count())
+        .map(|(_, filesystem)| filesystem);
+    filesystem.is_some_and(|filesystem| NETWORK_FILESYSTEMS.contains(&filesystem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescapes_mount_points() {
+        let unescape = unescape_mount_point;
+        assert_eq!(unescape("/mnt/anime"), PathBuf::from("/mnt/anime"));
+        assert_eq!(
+            unescape("/mnt/my\\040anime"),
+            PathBuf::from("/mnt/my anime")
+        );
+        assert_eq!(
+            unescape("/mnt/a\\011b\\012c"),
+            PathBuf::from("/mnt/a\tb\nc")
+        );
+        assert_eq!(
+            unescape("/mnt/back\\134slash"),
+            PathBuf::from("/mnt/back\\slash")
+        );
+        // Backslashes that don't start an octal escape are kept.
+        assert_eq!(unescape("/mnt/a\\x"), PathBuf::from("/mnt/a\\x"));
+        assert_eq!(unescape("/mnt/a\\09"), PathBuf::from("/mnt/a\\09"));
+        assert_eq!(unescape("/mnt/end\\"), PathBuf::from("/mnt/end\\"));
+        assert_eq!(unescape("/mnt/\\303\\251"), PathBuf::from("/mnt/é"));
+    }
+}